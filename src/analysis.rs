@@ -0,0 +1,1190 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs::File,
+    hash::{Hash, Hasher},
+    io::{BufReader, ErrorKind, Read},
+    ops::AddAssign,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use brickadia::save::{Brick, BrickColor, BrickOwner, Header2, SaveData, Size, UnrealType};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{brick_bounds, for_each_chunk, Bounds, ChunkPos, Grid},
+    config::Config,
+    stream::SaveStream,
+};
+
+pub const COLLIDER_LIMIT: u32 = 65000;
+pub const COMPONENT_LIMIT: u32 = 75;
+
+/// Substrings of component class names that belong to wires and logic bricks.
+const WIRE_COMPONENT_PATTERNS: [&str; 3] = ["Wire", "Logic", "Gate"];
+
+/// Component classes that are lights.
+const LIGHT_COMPONENTS: [&str; 2] = ["BCD_PointLight", "BCD_SpotLight"];
+
+/// The component class of audio emitters.
+const AUDIO_COMPONENT: &str = "BCD_AudioEmitter";
+
+/// Substrings of component class names that belong to particle and other visual effect
+/// emitters. Audio emitters are counted separately.
+const EMITTER_COMPONENT_PATTERNS: [&str; 3] = ["Particle", "VFX", "Emitter"];
+
+/// Substrings of component class names that players click to interact with, like buttons.
+const INTERACT_COMPONENT_PATTERNS: [&str; 2] = ["Interact", "Button"];
+
+/// Substrings of component class names that belong to item spawners and pickups.
+const SPAWNER_COMPONENT_PATTERNS: [&str; 2] = ["ItemSpawn", "Pickup"];
+
+/// Returns whether `brick` has both player and weapon collision turned off, so it doesn't
+/// generate the usual collider load.
+pub fn is_ghost(brick: &Brick) -> bool {
+    !brick.collision.player && !brick.collision.weapon
+}
+
+/// Returns whether the component class `name` is part of the wire/logic system.
+pub fn is_wire_component(name: &str) -> bool {
+    WIRE_COMPONENT_PATTERNS.iter().any(|p| name.contains(p))
+}
+
+/// The collider database compiled into the plugin, so it works without `colliders.json`.
+const DEFAULT_COLLIDERS: &str = include_str!("../colliders.json");
+
+/// The collider database file, which is merged over the built-in one if it exists.
+const COLLIDERS_PATH: &str = "colliders.json";
+
+/// Loads the collider counts of every known asset: the built-in database, with the entries of
+/// `colliders.json` merged on top if the file exists.
+fn load_colliders() -> Result<HashMap<String, u32>> {
+    let mut colliders = builtin_colliders();
+    match File::open(COLLIDERS_PATH) {
+        Ok(file) => {
+            let overrides: HashMap<String, u32> = serde_json::from_reader(BufReader::new(file))?;
+            colliders.extend(overrides);
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(colliders)
+}
+
+/// Returns the collider database compiled into the plugin.
+fn builtin_colliders() -> HashMap<String, u32> {
+    serde_json::from_str(DEFAULT_COLLIDERS).expect("the built-in collider database is invalid")
+}
+
+lazy_static! {
+    // a broken colliders.json falls back to the built-in database, so analyses keep working until
+    // it is fixed and reloaded. `reload_colliders` reports why it couldn't be read.
+    static ref BRICK_COLLIDERS: RwLock<HashMap<String, u32>> =
+        RwLock::new(load_colliders().unwrap_or_else(|_| builtin_colliders()));
+}
+
+/// Reloads the collider database from disk, returning how many assets it knows. The database is
+/// left untouched if `colliders.json` can't be read.
+pub fn reload_colliders() -> Result<usize> {
+    let colliders = load_colliders()?;
+    let count = colliders.len();
+    *BRICK_COLLIDERS.write().unwrap() = colliders;
+    Ok(count)
+}
+
+/// Kinds of procedural assets whose colliders grow differently with their size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AssetClass {
+    Standard,
+    Micro,
+    Rounded,
+    Wedge,
+}
+
+/// Substrings of asset names for each class besides `Standard`, checked in order.
+const ASSET_CLASS_PATTERNS: [(AssetClass, &[&str]); 3] = [
+    (AssetClass::Micro, &["Micro"]),
+    (AssetClass::Wedge, &["Wedge", "Ramp", "Corner"]),
+    (AssetClass::Rounded, &["Round", "Cylinder", "Sphere", "Arch", "Dome"]),
+];
+
+impl AssetClass {
+    fn of(asset: &str) -> Self {
+        ASSET_CLASS_PATTERNS
+            .iter()
+            .find(|(_, patterns)| patterns.iter().any(|p| asset.contains(p)))
+            .map_or(AssetClass::Standard, |(class, _)| *class)
+    }
+
+    /// The name used to refer to the class in the config.
+    fn name(self) -> &'static str {
+        match self {
+            AssetClass::Standard => "standard",
+            AssetClass::Micro => "micro",
+            AssetClass::Rounded => "rounded",
+            AssetClass::Wedge => "wedge",
+        }
+    }
+}
+
+/// Estimates how many colliders bricks generate.
+struct ColliderCosts {
+    /// The base collider count of every asset in the save, by asset index.
+    assets: Vec<u32>,
+    /// Whether each asset in the save is missing from the collider database, by asset index.
+    unknown: Vec<bool>,
+    /// Extra colliders per stud of a procedural brick's footprint and per plate of its height,
+    /// by asset index, depending on the asset's class.
+    size_costs: Vec<(f64, f64)>,
+    /// How much of their collider count ghost bricks (see `is_ghost`) keep.
+    ghost_scale: f64,
+}
+
+impl ColliderCosts {
+    /// Resolves the collider count of every asset in `brick_assets`, so they can be looked up by
+    /// asset index instead of by name for every brick.
+    fn new(brick_assets: &[String], config: &Config) -> Self {
+        let mut colliders = BRICK_COLLIDERS.read().unwrap().clone();
+        colliders.extend(config.collider_override_map());
+        let class_costs = config.asset_class_cost_map();
+        Self {
+            assets: brick_assets
+                .iter()
+                .map(|asset| {
+                    *colliders
+                        .get(asset.as_str())
+                        .unwrap_or(&config.unknown_collider_cost)
+                })
+                .collect(),
+            unknown: brick_assets
+                .iter()
+                .map(|asset| !colliders.contains_key(asset.as_str()))
+                .collect(),
+            size_costs: brick_assets
+                .iter()
+                .map(|asset| {
+                    *class_costs
+                        .get(AssetClass::of(asset).name())
+                        .unwrap_or(&(config.stud_collider_cost, config.plate_collider_cost))
+                })
+                .collect(),
+            ghost_scale: config.ghost_collider_scale,
+        }
+    }
+
+    /// Returns the estimated collider count of `brick`. Procedural bricks cost their asset's
+    /// base count plus an amount scaled by their size, depending on the asset's class, and ghost
+    /// bricks are discounted.
+    fn brick(&self, brick: &Brick) -> u32 {
+        let asset = brick.asset_name_index as usize;
+        let base = *self.assets.get(asset).unwrap_or(&1);
+        let colliders = match brick.size {
+            Size::Procedural(x, y, z) => {
+                // a stud is 10 units wide and a plate is 4 units tall, sizes are half extents
+                let (per_stud, per_plate) = self.size_costs.get(asset).copied().unwrap_or_default();
+                let studs = (x as f64 / 5.0) * (y as f64 / 5.0);
+                let plates = z as f64 / 2.0;
+                base + (studs * per_stud + plates * per_plate).round() as u32
+            }
+            Size::Empty => base,
+        };
+
+        if is_ghost(brick) {
+            (colliders as f64 * self.ghost_scale).round() as u32
+        } else {
+            colliders
+        }
+    }
+}
+
+/// Rough triangle counts used to estimate how expensive bricks are to render.
+const BOX_TRIANGLES: u32 = 12;
+const STUD_TRIANGLES: u32 = 24;
+const STATIC_MESH_TRIANGLES: u32 = 150;
+
+/// Substrings of procedural asset names that don't have studs on top.
+const STUDLESS_ASSET_PATTERNS: [&str; 3] = ["Tile", "Micro", "Smooth"];
+
+/// What a single brick contributes to every chunk it touches.
+#[derive(Debug, Clone, Copy)]
+struct BrickInfo {
+    bounds: Bounds,
+    colliders: u32,
+    glow: bool,
+    stray: bool,
+    triangles: u32,
+    bytes: u32,
+}
+
+/// Returns how many bits the save format uses for an integer that is less than `max`.
+fn uint_bits(max: usize) -> u32 {
+    (max.max(2) as u32 - 1).ilog2() + 1
+}
+
+/// Returns how many bits the save format uses for a packed integer.
+fn packed_bits(value: u32) -> u32 {
+    8 * (value.max(1).ilog2() + 1).div_ceil(7)
+}
+
+/// Returns roughly how many bytes a component property's value takes up in a save.
+fn unreal_type_bytes(value: &UnrealType) -> u32 {
+    match value {
+        UnrealType::Class(s) | UnrealType::String(s) => 5 + s.len() as u32,
+        UnrealType::Boolean(_) | UnrealType::Float(_) | UnrealType::Color(_) => 4,
+        UnrealType::Byte(_) => 1,
+        UnrealType::Rotator(..) => 12,
+    }
+}
+
+/// Restricts an analysis to some of the save's bricks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrickFilter {
+    /// Only analyze the bricks of the owner with this name, ignoring case.
+    pub owner: Option<String>,
+    /// Only analyze bricks overlapping the box between these world positions, inclusive.
+    pub region: Option<Bounds>,
+}
+
+impl BrickFilter {
+    /// Sets the region to the box with corners `a` and `b`, in any order.
+    pub fn set_region(&mut self, a: (i32, i32, i32), b: (i32, i32, i32)) {
+        self.region = Some((
+            (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        ));
+    }
+
+    /// Describes what the filter lets through, if it filters anything at all.
+    pub fn describe(&self) -> Option<String> {
+        let mut description = "bricks".to_owned();
+        if let Some(owner) = &self.owner {
+            description += &format!(" owned by {}", owner);
+        }
+        if let Some((min, max)) = self.region {
+            description += &format!(" between {:?} and {:?}", min, max);
+        }
+        (self.owner.is_some() || self.region.is_some()).then_some(description)
+    }
+}
+
+/// Lookup tables built from a save's headers, used to work out what each brick contributes.
+struct Analyzer {
+    costs: ColliderCosts,
+    grid: Grid,
+    /// The owner indices of the bricks to analyze, if only some owners' bricks are analyzed.
+    owners: Option<HashSet<u32>>,
+    /// The box bricks have to overlap to be analyzed, if any.
+    region: Option<Bounds>,
+    /// How far bricks can be from the origin before they are strays, or 0 if there is no limit.
+    world_bound: i32,
+    /// Whether each material in the save is a glow material, by material index.
+    glow_materials: Vec<bool>,
+    /// Whether each asset in the save has studs when procedural, by asset index.
+    studded_assets: Vec<bool>,
+    /// The number of bits every brick uses for its asset, material, physical material, and
+    /// palette color indices.
+    index_bits: (u32, u32, u32, u32),
+}
+
+impl Analyzer {
+    fn new(header2: &Header2, config: &Config, grid: &Grid, filter: &BrickFilter) -> Self {
+        Self {
+            costs: ColliderCosts::new(&header2.brick_assets, config),
+            grid: *grid,
+            owners: filter.owner.as_ref().map(|name| {
+                header2
+                    .brick_owners
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, owner)| owner.name.eq_ignore_ascii_case(name))
+                    .map(|(i, _)| i as u32 + 1)
+                    .collect()
+            }),
+            region: filter.region,
+            world_bound: config.world_bound,
+            glow_materials: header2
+                .materials
+                .iter()
+                .map(|m| m.contains("Glow"))
+                .collect(),
+            studded_assets: header2
+                .brick_assets
+                .iter()
+                .map(|a| !STUDLESS_ASSET_PATTERNS.iter().any(|p| a.contains(p)))
+                .collect(),
+            index_bits: (
+                uint_bits(header2.brick_assets.len()),
+                uint_bits(header2.materials.len()),
+                uint_bits(header2.physical_materials.len()),
+                uint_bits(header2.colors.len()),
+            ),
+        }
+    }
+
+    /// Returns whether `brick` passes the analysis' filter.
+    fn includes(&self, brick: &Brick) -> bool {
+        let owned = self
+            .owners
+            .as_ref()
+            .is_none_or(|owners| owners.contains(&brick.owner_index));
+        let inside = self.region.is_none_or(|(rmin, rmax)| {
+            let (min, max) = brick_bounds(brick);
+            min.0 <= rmax.0
+                && max.0 >= rmin.0
+                && min.1 <= rmax.1
+                && max.1 >= rmin.1
+                && min.2 <= rmax.2
+                && max.2 >= rmin.2
+        });
+        owned && inside
+    }
+
+    /// Returns whether `brick` is further from the origin than the world bound along any axis.
+    fn is_stray(&self, brick: &Brick) -> bool {
+        let (x, y, z) = brick.position;
+        self.world_bound > 0 && [x, y, z].iter().any(|n| n.unsigned_abs() > self.world_bound as u32)
+    }
+
+    /// Estimates how many bytes `brick`'s record takes up in the (uncompressed) save, not
+    /// counting its components.
+    fn bytes(&self, brick: &Brick) -> u32 {
+        let (asset_bits, material_bits, physical_bits, color_bits) = self.index_bits;
+        let size_bits = match brick.size {
+            Size::Procedural(x, y, z) => 1 + packed_bits(x) + packed_bits(y) + packed_bits(z),
+            Size::Empty => 1,
+        };
+        let (x, y, z) = brick.position;
+        let position_bits = [x, y, z]
+            .iter()
+            .map(|&n| packed_bits(n.unsigned_abs().saturating_mul(2) + 1))
+            .sum::<u32>();
+        let color_bits = match brick.color {
+            BrickColor::Unique(_) => 1 + 24,
+            BrickColor::Index(_) => 1 + color_bits,
+        };
+
+        // orientation (24), collision (4), visibility (1), and material intensity (11) are fixed
+        let bits = asset_bits
+            + size_bits
+            + position_bits
+            + 24
+            + 4
+            + 1
+            + material_bits
+            + physical_bits
+            + 11
+            + color_bits
+            + packed_bits(brick.owner_index);
+
+        // every brick is byte aligned
+        bits.div_ceil(8)
+    }
+
+    /// Estimates how many triangles `brick` renders with. Procedural bricks are a box plus their
+    /// studs, and static mesh bricks are assumed to be a fixed, fairly detailed mesh.
+    fn triangles(&self, brick: &Brick) -> u32 {
+        if !brick.visibility {
+            return 0;
+        }
+
+        match brick.size {
+            Size::Procedural(x, y, _) => {
+                let studded = *self
+                    .studded_assets
+                    .get(brick.asset_name_index as usize)
+                    .unwrap_or(&false);
+                let studs = if studded { (x / 5) * (y / 5) } else { 0 };
+                BOX_TRIANGLES + studs * STUD_TRIANGLES
+            }
+            Size::Empty => STATIC_MESH_TRIANGLES,
+        }
+    }
+
+    /// Returns the assets missing from the collider database along with how many bricks use them,
+    /// most common first. `asset_bricks` is the number of bricks using each asset, by index.
+    fn unknown_assets(&self, brick_assets: &[String], asset_bricks: &[u32]) -> Vec<(String, u32)> {
+        let mut unknown = brick_assets
+            .iter()
+            .zip(asset_bricks.iter())
+            .zip(self.costs.unknown.iter())
+            .filter(|((_, &count), &unknown)| unknown && count > 0)
+            .map(|((name, &count), _)| (name.clone(), count))
+            .collect::<Vec<_>>();
+        unknown.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        unknown
+    }
+
+    fn brick(&self, brick: &Brick) -> BrickInfo {
+        BrickInfo {
+            bounds: brick_bounds(brick),
+            colliders: self.costs.brick(brick),
+            glow: *self
+                .glow_materials
+                .get(brick.material_index as usize)
+                .unwrap_or(&false),
+            stray: self.is_stray(brick),
+            triangles: self.triangles(brick),
+            bytes: self.bytes(brick),
+        }
+    }
+}
+
+/// Finds bricks that repeat an earlier brick exactly, like those left behind by pasting a build
+/// twice. Only hashes of the bricks seen so far are kept, so a hash collision could rarely count a
+/// brick that isn't a duplicate.
+#[derive(Default)]
+struct DuplicateFinder {
+    seen: HashSet<u64>,
+}
+
+impl DuplicateFinder {
+    /// Returns whether a brick with the same asset, position, size, and orientation as `brick`
+    /// has been checked before.
+    fn check(&mut self, brick: &Brick) -> bool {
+        let mut hasher = DefaultHasher::new();
+        (
+            brick.asset_name_index,
+            brick.position,
+            &brick.size,
+            &brick.direction,
+            &brick.rotation,
+        )
+            .hash(&mut hasher);
+        !self.seen.insert(hasher.finish())
+    }
+}
+
+/// Brick, collider, and component counts.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Counts {
+    pub bricks: u32,
+    pub colliders: u32,
+    pub components: u32,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Self) {
+        self.bricks += other.bricks;
+        self.colliders += other.colliders;
+        self.components += other.components;
+    }
+}
+
+/// The fields of a brick its components are attributed by. Streaming analysis keeps these around
+/// for every brick, since components are only read after all of the bricks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct BrickKey {
+    owner: u32,
+    asset: u32,
+}
+
+impl From<&Brick> for BrickKey {
+    fn from(brick: &Brick) -> Self {
+        Self {
+            owner: brick.owner_index,
+            asset: brick.asset_name_index,
+        }
+    }
+}
+
+/// Everything known about a single chunk after analysis.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkStats {
+    pub bricks: u32,
+    pub colliders: u32,
+    pub components: u32,
+    /// The number of ghost bricks (see `is_ghost`) included in `bricks`.
+    pub ghost_bricks: u32,
+    /// The number of invisible (render-disabled) bricks included in `bricks`.
+    pub invisible_bricks: u32,
+    /// The number of bricks using a glow material included in `bricks`.
+    pub glow_bricks: u32,
+    /// The number of bricks included in `bricks` that exactly repeat another brick (see
+    /// `DuplicateFinder`).
+    #[serde(default)]
+    pub duplicate_bricks: u32,
+    /// The number of bricks included in `bricks` that are outside the configured world bound.
+    #[serde(default)]
+    pub stray_bricks: u32,
+    /// The total volume of the chunk's bricks' bounding boxes inside the chunk, in cubic world
+    /// units. Overlapping bricks are counted separately, and static meshes count as a single unit.
+    #[serde(default)]
+    pub brick_volume: u64,
+    /// An estimate of how many triangles the chunk's bricks render with.
+    pub triangles: u64,
+    /// An estimate of how many bytes the chunk's brick records take up in the save.
+    pub brick_bytes: u64,
+    /// An estimate of how many bytes the chunk's component data takes up in the save.
+    pub component_bytes: u64,
+    /// The number of wire and logic components, which are tracked apart from `components`
+    /// because they cost far more to simulate.
+    pub wires: u32,
+    /// The number of light components.
+    pub lights: u32,
+    /// The number of audio emitter components.
+    pub audio_emitters: u32,
+    /// The number of item spawner and pickup components.
+    pub spawners: u32,
+    /// The number of interact and button components.
+    #[serde(default)]
+    pub interacts: u32,
+    /// The number of particle and visual effect emitter components.
+    #[serde(default)]
+    pub emitters: u32,
+    /// The distinct (material index, material intensity) pairs used by bricks in the chunk.
+    pub materials: HashSet<(u32, u32)>,
+    /// The counts contributed by each brick owner, by owner index (see `AnalyzedSave::owner_name`).
+    pub owners: HashMap<u32, Counts>,
+    /// The counts contributed by each brick asset, by asset index (see `AnalyzedSave::assets`).
+    pub assets: HashMap<u32, Counts>,
+    /// The number of components of each type, by component class (like `BCD_Interact`).
+    pub component_types: HashMap<String, u32>,
+}
+
+impl ChunkStats {
+    /// Returns an estimate of how many bytes the chunk takes up in the save.
+    pub fn save_bytes(&self) -> u64 {
+        self.brick_bytes + self.component_bytes
+    }
+
+    /// Returns how densely packed the chunk's bricks are, in bricks per cubic stud (a thousand
+    /// cubic world units) of the space they take up.
+    pub fn density(&self) -> f64 {
+        if self.brick_volume == 0 {
+            return 0.0;
+        }
+        self.bricks as f64 / (self.brick_volume as f64 / 1000.0)
+    }
+
+    /// Returns the owner index of the owner with the most bricks in the chunk, if it has any.
+    pub fn dominant_owner(&self) -> Option<u32> {
+        self.owners
+            .iter()
+            .max_by(|a, b| a.1.bricks.cmp(&b.1.bricks).then(b.0.cmp(a.0)))
+            .map(|(owner, _)| *owner)
+    }
+
+    /// Returns the chunk's component types, most common first.
+    pub fn top_component_types(&self) -> Vec<(&str, u32)> {
+        let mut types = self
+            .component_types
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count))
+            .collect::<Vec<_>>();
+        types.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        types
+    }
+
+    /// Adds a brick to the chunk, `volume` of which is inside the chunk. Its components are added
+    /// separately with `add_component`.
+    fn add_brick(&mut self, brick: &Brick, info: BrickInfo, volume: u64) {
+        let colliders = info.colliders;
+        self.bricks += 1;
+        self.brick_volume += volume;
+        self.colliders += colliders;
+        if is_ghost(brick) {
+            self.ghost_bricks += 1;
+        }
+        if !brick.visibility {
+            self.invisible_bricks += 1;
+        }
+        if info.glow {
+            self.glow_bricks += 1;
+        }
+        if info.stray {
+            self.stray_bricks += 1;
+        }
+        self.triangles += info.triangles as u64;
+        self.brick_bytes += info.bytes as u64;
+        self.materials
+            .insert((brick.material_index, brick.material_intensity));
+
+        for counts in [
+            self.owners.entry(brick.owner_index).or_default(),
+            self.assets.entry(brick.asset_name_index).or_default(),
+        ] {
+            counts.bricks += 1;
+            counts.colliders += colliders;
+        }
+    }
+
+    /// Adds a component named `name` with properties `props` on the brick `key`.
+    fn add_component(&mut self, key: BrickKey, name: &str, props: &HashMap<String, UnrealType>) {
+        self.components += 1;
+        self.component_bytes += props.values().map(unreal_type_bytes).sum::<u32>() as u64;
+        if is_wire_component(name) {
+            self.wires += 1;
+        }
+        if LIGHT_COMPONENTS.contains(&name) {
+            self.lights += 1;
+        }
+        if name == AUDIO_COMPONENT {
+            self.audio_emitters += 1;
+        } else if EMITTER_COMPONENT_PATTERNS.iter().any(|p| name.contains(p)) {
+            self.emitters += 1;
+        }
+        if SPAWNER_COMPONENT_PATTERNS.iter().any(|p| name.contains(p)) {
+            self.spawners += 1;
+        }
+        if INTERACT_COMPONENT_PATTERNS.iter().any(|p| name.contains(p)) {
+            self.interacts += 1;
+        }
+        match self.component_types.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.component_types.insert(name.to_owned(), 1);
+            }
+        }
+        self.owners.entry(key.owner).or_default().components += 1;
+        self.assets.entry(key.asset).or_default().components += 1;
+    }
+
+    /// Maps the chunk's owner and asset indices to `owners[index]` and `assets[index]`, for moving
+    /// it into an analysis with other owner and asset tables. Indices missing from the tables
+    /// become unknown.
+    fn remap(&mut self, owners: &[u32], assets: &[u32]) {
+        let remap = |counts: &mut HashMap<u32, Counts>, indices: &[u32]| {
+            let mut remapped = HashMap::<u32, Counts>::new();
+            for (index, counts) in counts.drain() {
+                let index = indices.get(index as usize).copied().unwrap_or(u32::MAX);
+                *remapped.entry(index).or_default() += counts;
+            }
+            *counts = remapped;
+        };
+        remap(&mut self.owners, owners);
+        remap(&mut self.assets, assets);
+    }
+
+    /// Adds all of `other`'s stats to this chunk's.
+    fn merge(&mut self, other: ChunkStats) {
+        self.bricks += other.bricks;
+        self.colliders += other.colliders;
+        self.components += other.components;
+        self.ghost_bricks += other.ghost_bricks;
+        self.invisible_bricks += other.invisible_bricks;
+        self.glow_bricks += other.glow_bricks;
+        self.duplicate_bricks += other.duplicate_bricks;
+        self.stray_bricks += other.stray_bricks;
+        self.brick_volume += other.brick_volume;
+        self.triangles += other.triangles;
+        self.brick_bytes += other.brick_bytes;
+        self.component_bytes += other.component_bytes;
+        self.wires += other.wires;
+        self.lights += other.lights;
+        self.audio_emitters += other.audio_emitters;
+        self.spawners += other.spawners;
+        self.interacts += other.interacts;
+        self.emitters += other.emitters;
+        self.materials.extend(other.materials);
+        for (owner, counts) in other.owners.into_iter() {
+            *self.owners.entry(owner).or_default() += counts;
+        }
+        for (asset, counts) in other.assets.into_iter() {
+            *self.assets.entry(asset).or_default() += counts;
+        }
+        for (name, count) in other.component_types.into_iter() {
+            *self.component_types.entry(name).or_default() += count;
+        }
+    }
+}
+
+/// How many bricks workers analyze between progress updates.
+const PROGRESS_BATCH: usize = 4096;
+
+/// How far along an analysis is, shared with whoever is reporting on it.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// The number of bricks in the save, once known.
+    pub total: AtomicUsize,
+    /// The number of bricks analyzed so far.
+    pub bricks: AtomicUsize,
+}
+
+/// The per-chunk stats of some of a save's bricks, which is all an analysis keeps of them.
+#[derive(Default)]
+struct Aggregate {
+    chunks: HashMap<ChunkPos, ChunkStats>,
+    /// The number of bricks analyzed.
+    bricks: u32,
+    /// The number of analyzed bricks that repeat another brick.
+    duplicates: u32,
+    /// The number of analyzed bricks using each asset, by asset index.
+    asset_bricks: Vec<u32>,
+}
+
+impl Aggregate {
+    fn new(asset_count: usize) -> Self {
+        Self {
+            asset_bricks: vec![0; asset_count],
+            ..Default::default()
+        }
+    }
+
+    /// Counts an analyzed brick towards the totals. Its chunks are updated separately.
+    fn count_brick(&mut self, brick: &Brick) {
+        self.bricks += 1;
+        if let Some(count) = self.asset_bricks.get_mut(brick.asset_name_index as usize) {
+            *count += 1;
+        }
+    }
+
+    /// Adds all of `other`'s stats to this aggregate's.
+    fn merge(&mut self, other: Aggregate) {
+        for (chunk_pos, stats) in other.chunks.into_iter() {
+            self.chunks.entry(chunk_pos).or_default().merge(stats);
+        }
+        self.bricks += other.bricks;
+        self.duplicates += other.duplicates;
+        for (count, other) in self.asset_bricks.iter_mut().zip(other.asset_bricks) {
+            *count += other;
+        }
+    }
+}
+
+/// Hands memory freed by an analysis back to the OS. The allocator would otherwise keep the
+/// pages of a large save around after it is dropped, even though only the much smaller
+/// aggregates are left.
+fn release_freed_memory() {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    // SAFETY: malloc_trim only returns unused pages of the heap to the OS
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+/// Folds `bricks` into per-chunk stats. Bricks spanning several chunks are counted in every
+/// chunk they touch.
+fn aggregate_bricks(
+    bricks: &[Brick],
+    analyzer: &Analyzer,
+    asset_count: usize,
+    progress: &Progress,
+) -> Aggregate {
+    let mut aggregate = Aggregate::new(asset_count);
+    for batch in bricks.chunks(PROGRESS_BATCH) {
+        for brick in batch.iter().filter(|brick| analyzer.includes(brick)) {
+            aggregate.count_brick(brick);
+            let info = analyzer.brick(brick);
+            let (min, max) = analyzer.grid.brick_chunk_bounds(brick);
+            for_each_chunk(min, max, |chunk_pos| {
+                let volume = analyzer.grid.overlap_volume(info.bounds, chunk_pos);
+                let stats = aggregate.chunks.entry(chunk_pos).or_default();
+                stats.add_brick(brick, info, volume);
+                for (name, props) in brick.components.iter() {
+                    stats.add_component(brick.into(), name, props);
+                }
+            });
+        }
+        progress.bricks.fetch_add(batch.len(), Ordering::Relaxed);
+    }
+    aggregate
+}
+
+/// Counts the bricks in `bricks` that repeat an earlier brick, in every chunk they touch. This
+/// has to see every brick, so it isn't split between workers like `aggregate_bricks`.
+fn find_duplicates(bricks: &[Brick], analyzer: &Analyzer) -> Aggregate {
+    let mut finder = DuplicateFinder::default();
+    let mut aggregate = Aggregate::default();
+    for brick in bricks.iter().filter(|brick| analyzer.includes(brick)) {
+        if finder.check(brick) {
+            aggregate.duplicates += 1;
+            let (min, max) = analyzer.grid.brick_chunk_bounds(brick);
+            for_each_chunk(min, max, |chunk_pos| {
+                aggregate.chunks.entry(chunk_pos).or_default().duplicate_bricks += 1;
+            });
+        }
+    }
+    aggregate
+}
+
+/// Serializes the chunk map as a list of entries, since JSON can't have tuples as keys.
+mod chunk_entries {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ChunkStats;
+    use crate::chunk::ChunkPos;
+
+    pub fn serialize<S: Serializer>(
+        chunks: &HashMap<ChunkPos, ChunkStats>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        chunks.iter().collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<ChunkPos, ChunkStats>, D::Error> {
+        Ok(Vec::<(ChunkPos, ChunkStats)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnalyzedSave {
+    #[serde(with = "chunk_entries")]
+    pub chunks: HashMap<ChunkPos, ChunkStats>,
+    /// The number of bricks analyzed. Bricks spanning several chunks are counted in each of them,
+    /// so this can be less than the sum of the chunks' brick counts.
+    pub brick_count: u32,
+    /// The number of analyzed bricks that exactly repeat another brick, counted once each.
+    #[serde(default)]
+    pub duplicate_bricks: u32,
+    /// The names of the save's brick owners, by owner index.
+    pub owners: Vec<String>,
+    /// Which of the save's bricks were analyzed.
+    #[serde(default)]
+    pub filter: BrickFilter,
+    /// The grid the save's bricks were divided into chunks with.
+    #[serde(default)]
+    pub grid: Grid,
+    /// The names of the save's brick assets, by asset index.
+    pub assets: Vec<String>,
+    /// The assets missing from the collider database, which were counted as the configured
+    /// `unknown_collider_cost`, along with how many bricks use them. Most common first.
+    pub unknown_assets: Vec<(String, u32)>,
+    /// When the save was analyzed, in seconds since the Unix epoch, or 0 if unknown.
+    #[serde(default)]
+    pub analyzed_at: u64,
+    /// Whether the analysis was restored from before the plugin restarted, so the save may have
+    /// changed since.
+    #[serde(skip)]
+    pub restored: bool,
+    /// Whether the analysis was imported from a file, so it may not be of this server's save.
+    #[serde(skip)]
+    pub imported: bool,
+}
+
+impl AnalyzedSave {
+    /// Analyzes a save straight from `reader`, folding bricks into the chunk map as they are
+    /// decoded instead of reading the whole save into memory first.
+    pub fn from_stream(
+        reader: impl Read,
+        config: &Config,
+        grid: &Grid,
+        filter: &BrickFilter,
+        progress: &Progress,
+    ) -> Result<Self> {
+        let mut stream = SaveStream::open(reader)?;
+        let analyzer = Analyzer::new(&stream.header2, config, grid, filter);
+        progress
+            .total
+            .store(stream.header1.brick_count as usize, Ordering::Relaxed);
+
+        // components are stored after every brick, so remember which chunks each brick went to.
+        // Most bricks share their chunks and key with many others, so every brick only keeps an
+        // index into a table of the distinct ones.
+        let mut aggregate = Aggregate::new(stream.header2.brick_assets.len());
+        let mut duplicates = DuplicateFinder::default();
+        let mut slots = vec![];
+        let mut slot_indices = HashMap::new();
+        let mut brick_slots = Vec::with_capacity(stream.header1.brick_count as usize);
+        let brick_count = stream.read_bricks(|brick| {
+            progress.bricks.fetch_add(1, Ordering::Relaxed);
+            if !analyzer.includes(&brick) {
+                brick_slots.push(u32::MAX);
+                return;
+            }
+            aggregate.count_brick(&brick);
+            let info = analyzer.brick(&brick);
+            let duplicate = duplicates.check(&brick);
+            aggregate.duplicates += duplicate as u32;
+            let (min, max) = analyzer.grid.brick_chunk_bounds(&brick);
+            for_each_chunk(min, max, |chunk_pos| {
+                let volume = analyzer.grid.overlap_volume(info.bounds, chunk_pos);
+                let stats = aggregate.chunks.entry(chunk_pos).or_default();
+                stats.add_brick(&brick, info, volume);
+                stats.duplicate_bricks += duplicate as u32;
+            });
+            let slot = (min, max, BrickKey::from(&brick));
+            brick_slots.push(*slot_indices.entry(slot).or_insert_with(|| {
+                slots.push(slot);
+                slots.len() as u32 - 1
+            }));
+        })?;
+        drop(slot_indices);
+        drop(duplicates);
+        stream.read_components(brick_count, |index, name, props| {
+            let (min, max, key) = match slots.get(brick_slots[index] as usize) {
+                Some(slot) => *slot,
+                None => return,
+            };
+            for_each_chunk(min, max, |chunk_pos| {
+                aggregate.chunks.entry(chunk_pos).or_default().add_component(key, name, props)
+            });
+        })?;
+        drop(brick_slots);
+
+        let analyzed = Self::from_aggregate(aggregate, &analyzer, stream.header2, filter);
+        release_freed_memory();
+        Ok(analyzed)
+    }
+
+    /// Analyzes a save that has been read into memory, splitting the work across threads. The
+    /// save is dropped as soon as its bricks are aggregated.
+    pub fn from_save(
+        data: SaveData,
+        config: &Config,
+        grid: &Grid,
+        filter: &BrickFilter,
+        progress: &Progress,
+    ) -> Self {
+        let SaveData {
+            header2, bricks, ..
+        } = data;
+        let analyzer = Analyzer::new(&header2, config, grid, filter);
+        progress.total.store(bricks.len(), Ordering::Relaxed);
+
+        // split the bricks evenly between one worker per core, then merge their aggregates.
+        // Duplicates are found by another thread alongside them, since it needs every brick.
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let batch_size = bricks.len().div_ceil(workers).max(1);
+        let asset_count = header2.brick_assets.len();
+        let analyzer_ref = &analyzer;
+        let partials = thread::scope(|s| {
+            let duplicates = s.spawn(|| find_duplicates(&bricks, analyzer_ref));
+            bricks
+                .chunks(batch_size)
+                .map(|bricks| {
+                    s.spawn(move || aggregate_bricks(bricks, analyzer_ref, asset_count, progress))
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .chain(std::iter::once(duplicates))
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        drop(bricks);
+
+        let mut aggregate = Aggregate::new(asset_count);
+        for partial in partials.into_iter() {
+            aggregate.merge(partial);
+        }
+
+        let analyzed = Self::from_aggregate(aggregate, &analyzer, header2, filter);
+        release_freed_memory();
+        analyzed
+    }
+
+    /// Builds the analysis out of the aggregated stats of a save with the headers `header2`.
+    fn from_aggregate(
+        mut aggregate: Aggregate,
+        analyzer: &Analyzer,
+        header2: Header2,
+        filter: &BrickFilter,
+    ) -> Self {
+        aggregate.chunks.shrink_to_fit();
+        Self {
+            chunks: aggregate.chunks,
+            brick_count: aggregate.bricks,
+            duplicate_bricks: aggregate.duplicates,
+            owners: owner_names(&header2.brick_owners),
+            filter: filter.clone(),
+            grid: analyzer.grid,
+            unknown_assets: analyzer.unknown_assets(&header2.brick_assets, &aggregate.asset_bricks),
+            assets: header2.brick_assets,
+            analyzed_at: unix_now(),
+            restored: false,
+            imported: false,
+        }
+    }
+
+    /// Returns this analysis with the chunks between `min` and `max` replaced by those of
+    /// `update`, a newer analysis of at least those chunks with the same grid.
+    ///
+    /// The analyzed brick and duplicate counts are adjusted by how many bricks the replaced chunks
+    /// gained or lost, so they are only exact when no brick crosses the edge of the replaced
+    /// chunks.
+    pub fn with_chunks_from(&self, update: &AnalyzedSave, min: ChunkPos, max: ChunkPos) -> Self {
+        let in_range = |pos: &ChunkPos| {
+            (min.0..=max.0).contains(&pos.0)
+                && (min.1..=max.1).contains(&pos.1)
+                && (min.2..=max.2).contains(&pos.2)
+        };
+
+        // look up the update's owners and assets in this analysis's tables by name
+        let mut owners = self.owners.clone();
+        let mut assets = self.assets.clone();
+        let owner_indices = std::iter::once(0)
+            .chain(update.owners.iter().map(|name| name_index(&mut owners, name) + 1))
+            .collect::<Vec<_>>();
+        let asset_indices = update
+            .assets
+            .iter()
+            .map(|name| name_index(&mut assets, name))
+            .collect::<Vec<_>>();
+
+        let mut chunks = self
+            .chunks
+            .iter()
+            .filter(|(pos, _)| !in_range(pos))
+            .map(|(pos, stats)| (*pos, stats.clone()))
+            .collect::<HashMap<_, _>>();
+        let (removed, removed_duplicates) = self
+            .chunks
+            .iter()
+            .filter(|(pos, _)| in_range(pos))
+            .fold((0, 0), |(bricks, duplicates), (_, stats)| {
+                (bricks + stats.bricks, duplicates + stats.duplicate_bricks)
+            });
+        let (mut added, mut added_duplicates) = (0, 0);
+        for (pos, stats) in update.chunks.iter().filter(|(pos, _)| in_range(pos)) {
+            let mut stats = stats.clone();
+            stats.remap(&owner_indices, &asset_indices);
+            added += stats.bricks;
+            added_duplicates += stats.duplicate_bricks;
+            chunks.insert(*pos, stats);
+        }
+
+        let mut unknown_assets = self.unknown_assets.clone();
+        for (asset, count) in update.unknown_assets.iter() {
+            if !unknown_assets.iter().any(|(known, _)| known == asset) {
+                unknown_assets.push((asset.clone(), *count));
+            }
+        }
+
+        Self {
+            chunks,
+            brick_count: (self.brick_count + added).saturating_sub(removed),
+            duplicate_bricks: (self.duplicate_bricks + added_duplicates)
+                .saturating_sub(removed_duplicates),
+            owners,
+            filter: self.filter.clone(),
+            grid: self.grid,
+            assets,
+            unknown_assets,
+            analyzed_at: self.analyzed_at,
+            restored: self.restored,
+            imported: self.imported,
+        }
+    }
+
+    /// Returns the name of the owner with index `owner`.
+    pub fn owner_name(&self, owner: u32) -> &str {
+        match owner {
+            0 => "PUBLIC",
+            i => self
+                .owners
+                .get(i as usize - 1)
+                .map_or("Unknown", String::as_str),
+        }
+    }
+
+    /// Returns the name of the asset with index `asset`.
+    pub fn asset_name(&self, asset: u32) -> &str {
+        self.assets
+            .get(asset as usize)
+            .map_or("Unknown", String::as_str)
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Returns the index of `name` in `names`, adding it to the end if it isn't there yet.
+fn name_index(names: &mut Vec<String>, name: &str) -> u32 {
+    match names.iter().position(|n| n == name) {
+        Some(index) => index as u32,
+        None => {
+            names.push(name.to_owned());
+            names.len() as u32 - 1
+        }
+    }
+}
+
+/// Collects the names of `brick_owners`, which are indexed starting at 1 by bricks.
+fn owner_names(brick_owners: &[BrickOwner]) -> Vec<String> {
+    brick_owners.iter().map(|o| o.name.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Analyzes a save of `bricks` using `assets`, with the default config and grid.
+    fn analyze(assets: &[&str], bricks: Vec<Brick>) -> AnalyzedSave {
+        let mut data = SaveData {
+            bricks,
+            ..Default::default()
+        };
+        data.header2.brick_assets = assets.iter().map(|a| a.to_string()).collect();
+        AnalyzedSave::from_save(
+            data,
+            &Config::default(),
+            &Grid::default(),
+            &BrickFilter::default(),
+            &Progress::default(),
+        )
+    }
+
+    fn brick(position: (i32, i32, i32), size: Size) -> Brick {
+        Brick {
+            position,
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bricks_count_in_every_chunk_they_touch() {
+        let save = analyze(
+            &["PB_DefaultBrick"],
+            vec![
+                // straddles the border between chunks 0 and 1
+                brick((1024, 512, 512), Size::Procedural(10, 10, 10)),
+                // ends exactly on it
+                brick((1034, 512, 512), Size::Procedural(10, 10, 10)),
+                // spans eight chunks at once
+                brick((2048, 2048, 1024), Size::Procedural(5, 5, 5)),
+            ],
+        );
+        assert_eq!(save.brick_count, 3);
+        assert_eq!(save.chunks[&(0, 0, 0)].bricks, 1);
+        assert_eq!(save.chunks[&(1, 0, 0)].bricks, 2);
+        let corner = save.chunks.keys().filter(|(x, y, _)| *x >= 1 && *y >= 1).count();
+        assert_eq!(corner, 8);
+        assert_eq!(save.chunks.len(), 10);
+    }
+
+
+    #[test]
+    fn triangles_estimate_studs_meshes_and_hidden_bricks() {
+        let save = analyze(
+            &["PB_DefaultBrick", "PB_DefaultTile", "B_1x1_Round"],
+            vec![
+                // a 2x2 brick has four studs
+                brick((512, 512, 512), Size::Procedural(10, 10, 6)),
+                Brick {
+                    asset_name_index: 1,
+                    ..brick((1536, 512, 512), Size::Procedural(10, 10, 2))
+                },
+                Brick {
+                    asset_name_index: 2,
+                    ..brick((2560, 512, 512), Size::Empty)
+                },
+                Brick {
+                    visibility: false,
+                    ..brick((3584, 512, 512), Size::Procedural(10, 10, 6))
+                },
+            ],
+        );
+        let triangles = |x| save.chunks[&(x, 0, 0)].triangles;
+        assert_eq!(triangles(0), (BOX_TRIANGLES + 4 * STUD_TRIANGLES) as u64);
+        assert_eq!(triangles(1), BOX_TRIANGLES as u64);
+        assert_eq!(triangles(2), STATIC_MESH_TRIANGLES as u64);
+        assert_eq!(triangles(3), 0);
+    }
+}
@@ -0,0 +1,336 @@
+use std::str::FromStr;
+
+use crate::chunk::ChunkPos;
+
+/// The arguments of a `/chunks` subcommand, with its usage to answer missing or mistyped
+/// arguments with instead of failing. Subcommands pass every argument, so the subcommand is at 0
+/// and its own arguments start at 1. Options found by keyword, like `for <seconds>`, can be looked
+/// for in any part of them.
+pub struct Args<'a> {
+    args: &'a [String],
+    usage: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a [String], usage: &'a str) -> Self {
+        Args { args, usage }
+    }
+
+    /// Returns the usage of the subcommand, for arguments that are wrong in some other way.
+    pub fn usage(&self) -> String {
+        self.usage.to_owned()
+    }
+
+    /// Returns the argument at `i`, if there is one.
+    pub fn get(&self, i: usize) -> Option<&'a str> {
+        self.args.get(i).map(String::as_str)
+    }
+
+    /// Returns the argument at `i`, or the usage if it is missing.
+    pub fn required(&self, i: usize) -> Result<&'a str, String> {
+        self.get(i).ok_or_else(|| self.usage())
+    }
+
+    /// Parses the argument at `i`, or returns the usage if it is missing or isn't a `T`.
+    pub fn parse<T: FromStr>(&self, i: usize) -> Result<T, String> {
+        self.try_parse(i).ok_or_else(|| self.usage())
+    }
+
+    /// Parses the argument at `i`, if there is one and it is a `T`. For optional arguments that
+    /// can be left out in front of others.
+    pub fn try_parse<T: FromStr>(&self, i: usize) -> Option<T> {
+        self.get(i)?.parse().ok()
+    }
+
+    /// Parses the argument at `i` as a percentage, with or without a `%`.
+    pub fn try_percent(&self, i: usize) -> Option<f64> {
+        self.get(i)?.trim_end_matches('%').parse().ok()
+    }
+
+    /// Parses the chunk coordinates `x y z` starting at `i`.
+    pub fn chunk(&self, i: usize) -> Result<ChunkPos, String> {
+        Ok((self.parse(i)?, self.parse(i + 1)?, self.parse(i + 2)?))
+    }
+
+    /// Parses the chunk coordinates of two opposite corners of a box, `x1 y1 z1 x2 y2 z2`,
+    /// starting at `i`. Returns the lowest and highest corner of the box.
+    pub fn region(&self, i: usize) -> Result<(ChunkPos, ChunkPos), String> {
+        let (a, b) = (self.chunk(i)?, self.chunk(i + 3)?);
+        Ok((
+            (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        ))
+    }
+
+    /// Returns the arguments from `i` on, which are none past the end.
+    pub fn rest(&self, i: usize) -> &'a [String] {
+        self.args.get(i..).unwrap_or_default()
+    }
+
+    /// Returns the argument following the keyword `keyword`, if the keyword is there, or the
+    /// usage if nothing follows it.
+    pub fn after(&self, keyword: &str) -> Result<Option<&'a str>, String> {
+        match self.args.iter().position(|arg| arg.eq_ignore_ascii_case(keyword)) {
+            Some(i) => self.required(i + 1).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns whether `args` has the keyword `flag`, ignoring case.
+pub fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|arg| arg.eq_ignore_ascii_case(flag))
+}
+
+/// A `/chunks` subcommand, as `/chunks help` explains it.
+pub struct Subcommand {
+    pub name: &'static str,
+    /// What the subcommand does, in a line.
+    pub summary: &'static str,
+    /// Every form the subcommand takes, after `/chunks`, with what each does.
+    pub usages: &'static [(&'static str, &'static str)],
+}
+
+/// The options every marking subcommand takes.
+const MARK_OPTIONS: (&str, &str) = (
+    "mark ... [options]",
+    "Marking options: corners, edges, fill, or wedges, owners, by <metric>, heatmap, replace, space, blink, confirm, --dry-run, --to <save>, and for <seconds>.",
+);
+
+/// Every `/chunks` subcommand. Only the subcommands listed here are run, so `/chunks help` never
+/// lists one that doesn't exist or leaves one out.
+#[rustfmt::skip]
+pub const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "help",
+        summary: "List the subcommands, or explain one.",
+        usages: &[("help [subcommand]", "List every subcommand, or every form of one.")],
+    },
+    Subcommand {
+        name: "page",
+        summary: "Read more of a long result.",
+        usages: &[("page [n]", "Whisper the next page, or page n, of the latest result too long to whisper at once.")],
+    },
+    Subcommand {
+        name: "analyze",
+        summary: "Save the world or a save and analyze its chunks.",
+        usages: &[(
+            "analyze [save] [update] [owner name] [region x y z x y z] [chunks x y z x y z] [as name]",
+            "Analyze the world, or a save in the builds folder, optionally only one owner's bricks or a region, and keep it under a name.",
+        )],
+    },
+    Subcommand {
+        name: "status",
+        summary: "Show how old the analysis is and how many chunks are marked.",
+        usages: &[("status", "Show how old the latest analysis is, whether automatic analysis is on, and how many chunks are marked.")],
+    },
+    Subcommand {
+        name: "auto",
+        summary: "Show or control automatic analysis.",
+        usages: &[("auto [on|off|interval minutes]", "Show, turn on or off, or set the interval of automatic analysis.")],
+    },
+    Subcommand {
+        name: "grid",
+        summary: "List the grid presets, or analyze with another chunk size.",
+        usages: &[("grid [preset|default]", "List the grid presets, or switch to a preset's chunk size until going back to the default.")],
+    },
+    Subcommand {
+        name: "reloadcolliders",
+        summary: "Reload the collider database.",
+        usages: &[("reloadcolliders", "Reload colliders.json, which applies from the next analysis.")],
+    },
+    Subcommand {
+        name: "in",
+        summary: "Show the chunk you're in.",
+        usages: &[("in", "Show the coordinates of the chunk you're in.")],
+    },
+    Subcommand {
+        name: "count",
+        summary: "Count what's in the chunk you're in.",
+        usages: &[("count", "Count the bricks, colliders, components, and more in the chunk you're in, with its load score.")],
+    },
+    Subcommand {
+        name: "blame",
+        summary: "List who contributes the most to your chunk.",
+        usages: &[("blame", "List the brick owners contributing the most colliders to the chunk you're in.")],
+    },
+    Subcommand {
+        name: "assets",
+        summary: "List the assets contributing the most to your chunk.",
+        usages: &[("assets", "List the brick assets contributing the most colliders to the chunk you're in.")],
+    },
+    Subcommand {
+        name: "components",
+        summary: "List the components in your chunk.",
+        usages: &[("components", "List how many components of each type are in the chunk you're in.")],
+    },
+    Subcommand {
+        name: "unknowns",
+        summary: "List the assets missing from the collider database.",
+        usages: &[("unknowns", "List the assets missing from the collider database, with how many bricks use each.")],
+    },
+    Subcommand {
+        name: "strays",
+        summary: "List the chunks with bricks far outside the world.",
+        usages: &[("strays", "List the chunks with bricks further from the origin than the world bound.")],
+    },
+    Subcommand {
+        name: "diff",
+        summary: "Compare two analyses chunk by chunk.",
+        usages: &[("diff [name] [name] [metric]", "List the chunks that changed the most since the previous analysis, or between named analyses.")],
+    },
+    Subcommand {
+        name: "baseline",
+        summary: "Pin an analysis to track growth against.",
+        usages: &[
+            ("baseline set", "Pin the latest analysis as the baseline, kept across restarts."),
+            ("baseline compare [metric]", "List the chunks that grew or shrank the most since the baseline."),
+        ],
+    },
+    Subcommand {
+        name: "summary",
+        summary: "Report totals and a health score for the whole analysis.",
+        usages: &[("summary", "Report the bricks, occupied chunks, chunks over each limit, the worst chunk, and a health score.")],
+    },
+    Subcommand {
+        name: "stats",
+        summary: "Show how colliders and components are spread across chunks.",
+        usages: &[("stats", "Show the min, median, 90th and 99th percentile, and max collider and component counts.")],
+    },
+    Subcommand {
+        name: "top",
+        summary: "List the heaviest chunks.",
+        usages: &[("top [count] [metric|score]", "List the heaviest chunks by load score or a metric, 5 by default and at most 100.")],
+    },
+    Subcommand {
+        name: "export",
+        summary: "Write an analysis to a file.",
+        usages: &[("export json|csv|png [name] [layer z]", "Write the latest or a named analysis to the exports folder, PNGs optionally of one layer.")],
+    },
+    Subcommand {
+        name: "import",
+        summary: "Load an analysis exported as JSON.",
+        usages: &[("import file", "Load an analysis from the exports folder as the latest analysis.")],
+    },
+    Subcommand {
+        name: "mark",
+        summary: "Mark chunks with bricks colored by their load.",
+        usages: &[
+            ("mark [options]", "Mark the chunk you're in."),
+            ("mark above <percent> [metric] [options]", "Mark every chunk above a percentage of a limit."),
+            ("mark top <n> [options]", "Mark the n chunks with the highest load score."),
+            ("mark owner <name> [percent] [options]", "Mark the chunks where a player owns most of the bricks."),
+            ("mark radius <n> [options]", "Mark the chunks with bricks up to n chunks around you."),
+            ("mark neighbors [options]", "Mark the chunk you're in and every chunk touching it."),
+            ("mark layer [options]", "Mark every chunk at your height."),
+            ("mark region x1 y1 z1 x2 y2 z2 [options]", "Mark every chunk in a box of chunk coordinates."),
+            ("mark at x y z [options]", "Mark the chunk at some chunk coordinates."),
+            MARK_OPTIONS,
+        ],
+    },
+    Subcommand {
+        name: "markall",
+        summary: "Mark every chunk with bricks.",
+        usages: &[("markall [options]", "Mark every analyzed chunk with bricks."), MARK_OPTIONS],
+    },
+    Subcommand {
+        name: "markover",
+        summary: "Mark the chunks over a limit.",
+        usages: &[("markover [options]", "Mark only the chunks over the collider or component limit."), MARK_OPTIONS],
+    },
+    Subcommand {
+        name: "markfloor",
+        summary: "Tile the floor under every column of chunks.",
+        usages: &[("markfloor [z] [for <seconds>]", "Lay a tile colored by load score under every column of chunks with bricks.")],
+    },
+    Subcommand {
+        name: "minimap",
+        summary: "Build a small model of the build's load next to you.",
+        usages: &[("minimap <scale> [for <seconds>]", "Build a cube for every chunk with bricks next to you, scale (1 to 50) studs apart.")],
+    },
+    Subcommand {
+        name: "follow",
+        summary: "Outline the chunk you're in wherever you go.",
+        usages: &[("follow [on|off]", "Toggle outlining the edges of the chunk you're in as you move.")],
+    },
+    Subcommand {
+        name: "legend",
+        summary: "Show or hide what marker colors mean after marking.",
+        usages: &[("legend [on|off]", "Show, or turn on or off, explaining marker colors after you mark chunks.")],
+    },
+    Subcommand {
+        name: "palette",
+        summary: "Pick the colors your markers are drawn in.",
+        usages: &[("palette [name|default]", "List the palettes, or pick one, like those for color blindness.")],
+    },
+    Subcommand {
+        name: "undo",
+        summary: "Undo the latest marking.",
+        usages: &[("undo", "Put the markers of the chunks the latest marking changed back how they were.")],
+    },
+    Subcommand {
+        name: "clear",
+        summary: "Clear markers.",
+        usages: &[
+            ("clear", "Clear every marker."),
+            ("clear here", "Clear the markers of the chunk you're in."),
+            ("clear at x y z", "Clear the markers of the chunk at some chunk coordinates."),
+            ("clear region x1 y1 z1 x2 y2 z2", "Clear the markers in a box of chunk coordinates."),
+        ],
+    },
+];
+
+/// Finds the subcommand called `name`, ignoring case.
+pub fn find_subcommand(name: &str) -> Option<&'static Subcommand> {
+    SUBCOMMANDS.iter().find(|subcommand| subcommand.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        command.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parses_positional_arguments() {
+        let words = words("mark top 5 50% x");
+        let args = Args::new(&words, "usage");
+        assert_eq!(args.get(1), Some("top"));
+        assert_eq!(args.get(9), None);
+        assert_eq!(args.required(9), Err("usage".to_owned()));
+        assert_eq!(args.parse::<usize>(2), Ok(5));
+        assert_eq!(args.parse::<usize>(4), Err("usage".to_owned()));
+        assert_eq!(args.try_parse::<usize>(4), None);
+        assert_eq!(args.try_percent(3), Some(50.0));
+        assert_eq!(args.rest(3), &words[3..]);
+        assert!(args.rest(9).is_empty());
+    }
+
+    #[test]
+    fn parses_chunks_and_regions() {
+        let words = words("mark region 3 -1 0 -2 4 0 fill");
+        let args = Args::new(&words, "usage");
+        assert_eq!(args.chunk(2), Ok((3, -1, 0)));
+        assert_eq!(args.region(2), Ok(((-2, -1, 0), (3, 4, 0))));
+        assert_eq!(args.region(3), Err("usage".to_owned()));
+    }
+
+    #[test]
+    fn finds_keyword_options() {
+        let words = words("markall FOR 30 by");
+        let args = Args::new(&words, "usage");
+        assert_eq!(args.after("for"), Ok(Some("30")));
+        assert_eq!(args.after("--to"), Ok(None));
+        assert_eq!(args.after("by"), Err("usage".to_owned()));
+        assert!(has_flag(&words, "markAll"));
+        assert!(!has_flag(&words, "blink"));
+    }
+
+    #[test]
+    fn finds_subcommands_ignoring_case() {
+        assert_eq!(find_subcommand("MarkOver").map(|s| s.name), Some("markover"));
+        assert!(find_subcommand("nothing").is_none());
+    }
+}
@@ -0,0 +1,323 @@
+use std::convert::TryFrom;
+
+use brickadia::save::{Brick, Direction, Rotation, Size};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+pub const CHUNK_SIZE: i32 = 1024;
+
+pub type ChunkPos = (i32, i32, i32);
+
+/// The lowest and highest world positions inside a box, inclusive.
+pub type Bounds = ((i32, i32, i32), (i32, i32, i32));
+
+/// Which side of a chunk each of its corners is on, along each axis.
+#[rustfmt::skip]
+const CHUNK_CORNERS: [(i32, i32, i32); 8] = [
+    (-1, -1, -1),
+    ( 1, -1, -1),
+    (-1,  1, -1),
+    ( 1,  1, -1),
+    (-1, -1,  1),
+    ( 1, -1,  1),
+    (-1,  1,  1),
+    ( 1,  1,  1),
+];
+
+/// Returns the world-axis half extents of a brick, accounting for its orientation.
+///
+/// Static mesh bricks have no stored size, so they are treated as a point at their position.
+pub fn brick_extents(brick: &Brick) -> (i32, i32, i32) {
+    let (x, y, z) = match brick.size {
+        Size::Procedural(x, y, z) => (x as i32, y as i32, z as i32),
+        Size::Empty => return (0, 0, 0),
+    };
+
+    // rotation spins the brick around its up axis, swapping its x and y extents
+    let (x, y) = match brick.rotation {
+        Rotation::Deg90 | Rotation::Deg270 => (y, x),
+        Rotation::Deg0 | Rotation::Deg180 => (x, y),
+    };
+
+    // direction then points the brick's up axis along a world axis
+    match brick.direction {
+        Direction::XPositive | Direction::XNegative => (z, y, x),
+        Direction::YPositive | Direction::YNegative => (x, z, y),
+        Direction::ZPositive | Direction::ZNegative => (x, y, z),
+    }
+}
+
+/// Returns the bounds of a brick's bounding box.
+pub fn brick_bounds(brick: &Brick) -> Bounds {
+    let extents = brick_extents(brick);
+
+    // a brick ending exactly on a chunk border doesn't reach into the next chunk
+    let p = brick.position;
+    (
+        (p.0 - extents.0, p.1 - extents.1, p.2 - extents.2),
+        (
+            p.0 + (extents.0 - 1).max(0),
+            p.1 + (extents.1 - 1).max(0),
+            p.2 + (extents.2 - 1).max(0),
+        ),
+    )
+}
+
+/// How the world is divided into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grid {
+    /// The size of chunks along each axis, in world units.
+    #[serde(default = "default_size")]
+    pub size: (i32, i32, i32),
+    /// The world position of the corner shared by chunks (0, 0, 0) and (-1, -1, -1).
+    #[serde(default)]
+    pub origin: (i32, i32, i32),
+    /// Whether chunks are columns spanning every height, in which case every chunk has a z of 0.
+    #[serde(default)]
+    pub columns: bool,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            size: default_size(),
+            origin: (0, 0, 0),
+            columns: false,
+        }
+    }
+}
+
+impl Grid {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            size: (
+                config.chunk_size_x.max(1),
+                config.chunk_size_y.max(1),
+                config.chunk_size_z.max(1),
+            ),
+            origin: (config.grid_origin_x, config.grid_origin_y, config.grid_origin_z),
+            columns: config.column_mode,
+        }
+    }
+
+    /// Creates the grid of the preset called `name` in `config`, which only differs from the
+    /// configured grid in its chunk size.
+    pub fn preset(config: &Config, name: &str) -> Option<Self> {
+        let size = *config.grid_preset_map().get(&name.to_lowercase())?;
+        Some(Self { size, ..Self::new(config) })
+    }
+
+    /// Returns the chunk containing the world position `pos`.
+    pub fn pos_to_chunk(&self, pos: (i32, i32, i32)) -> ChunkPos {
+        // widened, since a far origin and a stray brick can be further apart than an i32 holds
+        let axis = |pos: i32, origin: i32, size: i32| {
+            let chunk = (pos as i64 - origin as i64).div_euclid(size as i64);
+            chunk.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+        };
+        let chunk = (
+            axis(pos.0, self.origin.0, self.size.0),
+            axis(pos.1, self.origin.1, self.size.1),
+            axis(pos.2, self.origin.2, self.size.2),
+        );
+        if self.columns {
+            (chunk.0, chunk.1, 0)
+        } else {
+            chunk
+        }
+    }
+
+    /// Returns the world position at the center of a chunk, cut off at the positions an i32 can
+    /// hold for chunks at the edges of the world. Chunks from players should be checked with
+    /// `contains_chunk` first.
+    pub fn chunk_center(&self, pos: ChunkPos) -> (i32, i32, i32) {
+        let axis = |pos: i32, origin: i32, size: i32| {
+            let center = origin as i64 + size as i64 / 2 + pos as i64 * size as i64;
+            center.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+        };
+        (
+            axis(pos.0, self.origin.0, self.size.0),
+            axis(pos.1, self.origin.1, self.size.1),
+            axis(pos.2, self.origin.2, self.size.2),
+        )
+    }
+
+    /// Returns whether the whole chunk `pos` is at positions an i32 can hold, so it can be marked.
+    pub fn contains_chunk(&self, pos: ChunkPos) -> bool {
+        self.chunk_range_bounds(pos, pos).is_some()
+    }
+
+    /// Returns the position of the `i`th corner of the chunk centered at `center`, `inset` units
+    /// inside its edges.
+    pub fn chunk_corner(&self, i: usize, center: (i32, i32, i32), inset: i32) -> (i32, i32, i32) {
+        let (x, y, z) = self.size;
+        let (cx, cy, cz) = CHUNK_CORNERS[i];
+        (
+            center.0 + cx * (x / 2 - inset),
+            center.1 + cy * (y / 2 - inset),
+            center.2 + cz * (z / 2 - inset),
+        )
+    }
+
+    /// Returns the lowest and highest chunk touched by a brick's bounding box.
+    pub fn brick_chunk_bounds(&self, brick: &Brick) -> (ChunkPos, ChunkPos) {
+        self.bounds_chunks(brick_bounds(brick))
+    }
+
+    /// Returns the bounds of the chunks between `min` and `max`, inclusive, or `None` if they reach
+    /// past the positions an i32 can hold. Columns span every height.
+    pub fn chunk_range_bounds(&self, min: ChunkPos, max: ChunkPos) -> Option<Bounds> {
+        let (lo, hi) = self.chunk_range_extent(min, max);
+        let fit = |(x, y, z): (i64, i64, i64)| {
+            Some((i32::try_from(x).ok()?, i32::try_from(y).ok()?, i32::try_from(z).ok()?))
+        };
+        Some((fit(lo)?, fit(hi)?))
+    }
+
+    /// Returns the bounds of the chunks between `min` and `max`, inclusive, cut off at the
+    /// positions an i32 can hold, where no brick can be anyway.
+    pub fn chunk_range_bounds_clamped(&self, min: ChunkPos, max: ChunkPos) -> Bounds {
+        let (lo, hi) = self.chunk_range_extent(min, max);
+        let clamp = |n: i64| n.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        ((clamp(lo.0), clamp(lo.1), clamp(lo.2)), (clamp(hi.0), clamp(hi.1), clamp(hi.2)))
+    }
+
+    /// Returns the bounds of the chunks between `min` and `max`, inclusive, widened so chunks at
+    /// the edges of the world can't overflow.
+    fn chunk_range_extent(&self, min: ChunkPos, max: ChunkPos) -> ((i64, i64, i64), (i64, i64, i64)) {
+        let (x, y, z) = (self.size.0 as i64, self.size.1 as i64, self.size.2 as i64);
+        let (ox, oy, oz) = (self.origin.0 as i64, self.origin.1 as i64, self.origin.2 as i64);
+        let (min_z, max_z) = if self.columns {
+            (i32::MIN as i64, i32::MAX as i64)
+        } else {
+            (oz + min.2 as i64 * z, oz + (max.2 as i64 + 1) * z - 1)
+        };
+        (
+            (ox + min.0 as i64 * x, oy + min.1 as i64 * y, min_z),
+            (ox + (max.0 as i64 + 1) * x - 1, oy + (max.1 as i64 + 1) * y - 1, max_z),
+        )
+    }
+
+    /// Returns the volume of the part of the box `bounds` inside the chunk `pos`, in cubic world
+    /// units.
+    pub fn overlap_volume(&self, bounds: Bounds, pos: ChunkPos) -> u64 {
+        let (min, max) = self.chunk_range_extent(pos, pos);
+        let overlap = |lo: i32, hi: i32, chunk_lo: i64, chunk_hi: i64| {
+            ((hi as i64).min(chunk_hi) - (lo as i64).max(chunk_lo) + 1).max(0) as u64
+        };
+        overlap(bounds.0 .0, bounds.1 .0, min.0, max.0)
+            * overlap(bounds.0 .1, bounds.1 .1, min.1, max.1)
+            * overlap(bounds.0 .2, bounds.1 .2, min.2, max.2)
+    }
+
+    /// Returns the lowest and highest chunk touched by the box `bounds`.
+    pub fn bounds_chunks(&self, bounds: Bounds) -> (ChunkPos, ChunkPos) {
+        (self.pos_to_chunk(bounds.0), self.pos_to_chunk(bounds.1))
+    }
+}
+
+/// Calls `f` with every chunk between `min` and `max`, inclusive.
+pub fn for_each_chunk(min: ChunkPos, max: ChunkPos, mut f: impl FnMut(ChunkPos)) {
+    for x in min.0..=max.0 {
+        for y in min.1..=max.1 {
+            for z in min.2..=max.2 {
+                f((x, y, z));
+            }
+        }
+    }
+}
+
+fn default_size() -> (i32, i32, i32) {
+    (CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brick(size: (u32, u32, u32), direction: Direction, rotation: Rotation) -> Brick {
+        Brick {
+            size: Size::Procedural(size.0, size.1, size.2),
+            direction,
+            rotation,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn brick_extents_follow_orientation() {
+        let extents = |direction, rotation| brick_extents(&brick((1, 2, 3), direction, rotation));
+        assert_eq!(extents(Direction::ZPositive, Rotation::Deg0), (1, 2, 3));
+        assert_eq!(extents(Direction::ZNegative, Rotation::Deg180), (1, 2, 3));
+        assert_eq!(extents(Direction::ZPositive, Rotation::Deg90), (2, 1, 3));
+        assert_eq!(extents(Direction::XPositive, Rotation::Deg0), (3, 2, 1));
+        assert_eq!(extents(Direction::XNegative, Rotation::Deg270), (3, 1, 2));
+        assert_eq!(extents(Direction::YPositive, Rotation::Deg0), (1, 3, 2));
+        assert_eq!(extents(Direction::YNegative, Rotation::Deg90), (2, 3, 1));
+        assert_eq!(brick_extents(&Brick { size: Size::Empty, ..Default::default() }), (0, 0, 0));
+    }
+
+    #[test]
+    fn pos_to_chunk_rounds_down_from_the_origin() {
+        let grid = Grid::default();
+        assert_eq!(grid.pos_to_chunk((0, 0, 0)), (0, 0, 0));
+        assert_eq!(grid.pos_to_chunk((1023, 1024, -1)), (0, 1, -1));
+        assert_eq!(grid.pos_to_chunk((-1024, -1025, 2048)), (-1, -2, 2));
+
+        let grid = Grid {
+            size: (100, 200, 300),
+            origin: (50, 0, -10),
+            columns: true,
+        };
+        assert_eq!(grid.pos_to_chunk((49, 199, 5000)), (-1, 0, 0));
+        assert_eq!(grid.pos_to_chunk((150, 200, -5000)), (1, 1, 0));
+
+        let grid = Grid {
+            size: (1, 1, 1),
+            origin: (i32::MAX, i32::MIN, 0),
+            columns: false,
+        };
+        assert_eq!(grid.pos_to_chunk((i32::MIN, i32::MAX, 0)), (i32::MIN, i32::MAX, 0));
+    }
+
+    #[test]
+    fn chunk_centers_stay_in_the_world() {
+        let grid = Grid::default();
+        assert_eq!(grid.chunk_center((0, -1, 2)), (512, -512, 2560));
+        assert!(grid.contains_chunk((0, -1, 2)));
+        assert!(!grid.contains_chunk((10_000_000, 0, 0)));
+        assert_eq!(grid.chunk_center((10_000_000, 0, 0)).0, i32::MAX);
+    }
+
+    #[test]
+    fn chunk_range_bounds_cover_whole_chunks() {
+        let grid = Grid::default();
+        assert_eq!(grid.chunk_range_bounds((0, 0, 0), (0, 0, 0)), Some(((0, 0, 0), (1023, 1023, 1023))));
+        assert_eq!(
+            grid.chunk_range_bounds((-1, 0, 2), (1, 0, 2)),
+            Some(((-1024, 0, 2048), (2047, 1023, 3071))),
+        );
+
+        let grid = Grid { columns: true, ..Grid::default() };
+        assert_eq!(
+            grid.chunk_range_bounds((0, 0, 0), (0, 0, 0)),
+            Some(((0, 0, i32::MIN), (1023, 1023, i32::MAX))),
+        );
+    }
+
+    #[test]
+    fn chunk_range_bounds_past_the_world_dont_overflow() {
+        let grid = Grid::default();
+        assert_eq!(grid.chunk_range_bounds((0, 0, 0), (10_000_000, 0, 0)), None);
+        assert_eq!(grid.chunk_range_bounds((i32::MIN, 0, 0), (0, 0, 0)), None);
+
+        // the last chunk an i32 reaches ends exactly at its highest position
+        let last = grid.pos_to_chunk((i32::MAX, i32::MAX, i32::MAX));
+        assert_eq!(grid.chunk_range_bounds(last, last).map(|(_, max)| max), Some((i32::MAX, i32::MAX, i32::MAX)));
+
+        let grid = Grid { size: (1000, 1000, 1000), ..Grid::default() };
+        let last = grid.pos_to_chunk((i32::MAX, 0, 0));
+        assert_eq!(grid.chunk_range_bounds(last, last), None);
+        assert_eq!(grid.chunk_range_bounds_clamped(last, last).1, (i32::MAX, 999, 999));
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+};
+
+use anyhow::{anyhow, bail, Result};
+use brickadia::read::SaveReader;
+
+use crate::{
+    analysis::{reload_colliders, AnalyzedSave, BrickFilter, Progress},
+    chunk::Grid,
+    config::Config,
+    metric::{health_rating, health_score, severity, Metric},
+};
+
+const USAGE: &str =
+    "usage: omegga-chunks analyze <file.brs> [--json] [--config <config.json>] [--owner <name>] [--grid <preset>]";
+
+/// Runs the analysis on a save file without omegga, printing the results to stdout. Returns
+/// whether any chunk is over a limit.
+pub fn run(args: &[String]) -> Result<bool> {
+    if args.first().map(String::as_str) != Some("analyze") {
+        bail!(USAGE);
+    }
+
+    let mut path = None;
+    let mut json = false;
+    let mut config_path = None;
+    let mut filter = BrickFilter::default();
+    let mut preset = None;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--config" => config_path = Some(rest.next().ok_or_else(|| anyhow!(USAGE))?),
+            "--owner" => filter.owner = Some(rest.next().ok_or_else(|| anyhow!(USAGE))?.clone()),
+            "--grid" => preset = Some(rest.next().ok_or_else(|| anyhow!(USAGE))?),
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!(USAGE),
+        }
+    }
+    let path = path.ok_or_else(|| anyhow!(USAGE))?;
+
+    // the config file is the same as the plugin's config, and is optional
+    let config: Config = match config_path {
+        Some(config_path) => serde_json::from_reader(BufReader::new(File::open(config_path)?))?,
+        None => Config::default(),
+    };
+
+    let grid = match preset {
+        Some(name) => Grid::preset(&config, name)
+            .ok_or_else(|| anyhow!("there is no grid preset named {}", name))?,
+        None => Grid::new(&config),
+    };
+
+    if let Err(e) = reload_colliders() {
+        eprintln!("failed to load colliders.json, using the built-in collider database: {}", e);
+    }
+
+    let progress = Progress::default();
+    let file = BufReader::new(File::open(path)?);
+    let save = if config.streaming_analysis {
+        AnalyzedSave::from_stream(file, &config, &grid, &filter, &progress)?
+    } else {
+        let data = SaveReader::new(file)?.read_all_skip_preview()?;
+        AnalyzedSave::from_save(data, &config, &grid, &filter, &progress)
+    };
+
+    let mut over_limit = save
+        .chunks
+        .iter()
+        .map(|(pos, stats)| (pos, stats, severity(stats, &config).0))
+        .filter(|(_, _, severity)| *severity > 1.0)
+        .collect::<Vec<_>>();
+    over_limit.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(b.0)));
+
+    if json {
+        serde_json::to_writer_pretty(io::stdout().lock(), &save)?;
+        println!();
+        return Ok(!over_limit.is_empty());
+    }
+
+    println!("{} bricks in {} chunks", save.brick_count, save.chunks.len());
+    let health = health_score(&save, &config);
+    println!("health {:.0}/100 ({})", health, health_rating(health).0);
+    if save.duplicate_bricks > 0 {
+        println!("{} duplicate bricks", save.duplicate_bricks);
+    }
+    for metric in Metric::ALL.iter() {
+        if let Some(limit) = metric.limit(&config) {
+            let count = save.chunks.values().filter(|s| metric.value(s) > limit).count();
+            println!("{} chunks over the {} limit ({})", count, metric.label(), limit);
+        }
+    }
+    for (asset, bricks) in save.unknown_assets.iter() {
+        println!("unknown asset {} used by {} bricks", asset, bricks);
+    }
+
+    if !over_limit.is_empty() {
+        println!();
+        println!("over-limit chunks:");
+    }
+    for (pos, stats, _) in over_limit.iter() {
+        let over = Metric::ALL
+            .iter()
+            .filter_map(|m| m.limit(&config).map(|limit| (m, limit)))
+            .filter(|(m, limit)| m.value(stats) > *limit)
+            .map(|(m, limit)| format!("{} {}/{}", m.label(), m.value(stats), limit))
+            .collect::<Vec<_>>();
+        println!("{:?}: {}", pos, over.join(", "));
+    }
+
+    Ok(!over_limit.is_empty())
+}
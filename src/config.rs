@@ -0,0 +1,484 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{chunk::CHUNK_SIZE, marker::MARKER_OWNER_UUID};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthUser {
+    pub name: String,
+    pub id: String,
+}
+
+/// What the colors of chunk markers show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkerColoring {
+    /// A gradient from green to red by the chunk's load score.
+    #[default]
+    Load,
+    /// A gradient from green to red by how close the chunk is to the collider or component
+    /// limit, whichever is closer.
+    Percent,
+    /// A gradient from green to red by how densely packed the chunk's bricks are, up to
+    /// `marker_density_full`.
+    Density,
+    /// A fixed color for each limit the chunk is over.
+    Limits,
+    /// A gradient of `heatmap_steps` steps by how the chunk's load score ranks among every
+    /// chunk of the analysis, with about as many chunks in each step.
+    Heatmap,
+}
+
+/// What chunk markers are drawn with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkerStyle {
+    /// A micro brick in each of the chunk's 8 corners.
+    #[default]
+    Corners,
+    /// Thin bricks along the chunk's 12 edges.
+    Edges,
+    /// A single translucent brick filling the whole chunk.
+    Fill,
+    /// A micro corner wedge in each of the chunk's 8 corners, sloping into the chunk so it is
+    /// clear which of two marked neighbors it belongs to.
+    Wedges,
+}
+
+impl MarkerStyle {
+    /// Finds the style called `name`, as it is written in the config.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "corners" => Some(MarkerStyle::Corners),
+            "edges" => Some(MarkerStyle::Edges),
+            "fill" => Some(MarkerStyle::Fill),
+            "wedges" => Some(MarkerStyle::Wedges),
+            _ => None,
+        }
+    }
+}
+
+/// Which colors chunk markers are drawn in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkerPalette {
+    /// Green, yellow, and red gradients, and bright primary colors for limits.
+    #[default]
+    Standard,
+    /// Safe for deuteranopia, the most common red-green color blindness.
+    Deuteranopia,
+    /// Safe for protanopia, red-green color blindness with reds looking dark.
+    Protanopia,
+    /// Safe for tritanopia, blue-yellow color blindness.
+    Tritanopia,
+}
+
+impl MarkerPalette {
+    pub const ALL: [MarkerPalette; 4] = [
+        MarkerPalette::Standard,
+        MarkerPalette::Deuteranopia,
+        MarkerPalette::Protanopia,
+        MarkerPalette::Tritanopia,
+    ];
+
+    /// The name of the palette, as it is written in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            MarkerPalette::Standard => "standard",
+            MarkerPalette::Deuteranopia => "deuteranopia",
+            MarkerPalette::Protanopia => "protanopia",
+            MarkerPalette::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Finds the palette called `name`, as it is written in the config.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(default)]
+    pub authorized: Vec<AuthUser>,
+    #[serde(default = "default_save_timeout")]
+    pub save_timeout: u64,
+    #[serde(default)]
+    pub streaming_analysis: bool,
+    /// The size of chunks along each axis, in world units.
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size_x: i32,
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size_y: i32,
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size_z: i32,
+    /// Where the chunk grid starts, in world units, to line chunk borders up with the world.
+    #[serde(default)]
+    pub grid_origin_x: i32,
+    #[serde(default)]
+    pub grid_origin_y: i32,
+    #[serde(default)]
+    pub grid_origin_z: i32,
+    /// Alternative chunk sizes to switch to with `/chunks grid`, as `name=size` or
+    /// `name=XxYxZ` entries.
+    #[serde(default)]
+    pub grid_presets: Vec<String>,
+    /// How far from the world origin bricks can be along any axis, in world units, before they
+    /// are reported as strays, or 0 to never report them.
+    #[serde(default = "default_world_bound")]
+    pub world_bound: i32,
+    /// Whether chunks are columns that merge every height, keyed by x and y alone.
+    #[serde(default)]
+    pub column_mode: bool,
+    /// How many minutes between automatic analyses, or 0 to only analyze on request.
+    #[serde(default)]
+    pub auto_analysis_interval: u64,
+    /// How many minutes old an analysis can get before commands warn that it may be out of date,
+    /// or 0 to never warn.
+    #[serde(default = "default_stale_analysis_minutes")]
+    pub stale_analysis_minutes: u64,
+    /// Whether to analyze the server's autosaves as they are written.
+    #[serde(default)]
+    pub autosave_analysis: bool,
+    /// A case insensitive substring of the paths of saves that count as autosaves.
+    #[serde(default = "default_autosave_pattern")]
+    pub autosave_pattern: String,
+    /// Collider counts of assets, as `asset=count` entries, merged over the collider database.
+    #[serde(default)]
+    pub collider_overrides: Vec<String>,
+    #[serde(default = "default_unknown_collider_cost")]
+    pub unknown_collider_cost: u32,
+    #[serde(default)]
+    pub stud_collider_cost: f64,
+    #[serde(default)]
+    pub plate_collider_cost: f64,
+    /// Per-stud and per-plate costs of asset classes, as `class=stud,plate` entries, used
+    /// instead of `stud_collider_cost` and `plate_collider_cost` for that class.
+    #[serde(default)]
+    pub asset_class_costs: Vec<String>,
+    #[serde(default)]
+    pub ghost_collider_scale: f64,
+    #[serde(default = "default_wire_limit")]
+    pub wire_limit: u32,
+    #[serde(default = "default_light_limit")]
+    pub light_limit: u32,
+    #[serde(default = "default_audio_limit")]
+    pub audio_limit: u32,
+    #[serde(default = "default_spawner_limit")]
+    pub spawner_limit: u32,
+    #[serde(default = "default_interact_limit")]
+    pub interact_limit: u32,
+    #[serde(default = "default_emitter_limit")]
+    pub emitter_limit: u32,
+    #[serde(default = "default_glow_limit")]
+    pub glow_limit: u32,
+    /// The estimated bytes of component data, like interact messages, a chunk can hold before
+    /// it counts as over the limit.
+    #[serde(default = "default_payload_limit")]
+    pub payload_limit: u32,
+    /// How much each metric counts towards the load score.
+    #[serde(default = "default_load_weight")]
+    pub load_weight_bricks: f64,
+    #[serde(default = "default_load_weight_colliders")]
+    pub load_weight_colliders: f64,
+    #[serde(default = "default_load_weight_components")]
+    pub load_weight_components: f64,
+    #[serde(default = "default_load_weight")]
+    pub load_weight_lights: f64,
+    #[serde(default = "default_load_weight")]
+    pub load_weight_wires: f64,
+    /// The total colliders and components across the whole save at which the health score
+    /// counts the server as fully loaded.
+    #[serde(default = "default_server_collider_budget")]
+    pub server_collider_budget: u64,
+    #[serde(default = "default_server_component_budget")]
+    pub server_component_budget: u64,
+    /// The number of bricks that counts as a fully loaded chunk in the load score.
+    #[serde(default = "default_load_brick_budget")]
+    pub load_brick_budget: u32,
+    #[serde(default)]
+    pub marker_coloring: MarkerColoring,
+    /// Colors of the chunk states with `limits` marker coloring, as `state=rrggbb` entries, used
+    /// instead of the built-in ones.
+    #[serde(default)]
+    pub marker_colors: Vec<String>,
+    /// How close to a limit, in percent, a chunk has to be for the `space` marking option to
+    /// mark the empty chunks around it.
+    #[serde(default = "default_heavy_chunk_percent")]
+    pub heavy_chunk_percent: f64,
+    /// How many color steps heatmap markers are split into.
+    #[serde(default = "default_heatmap_steps")]
+    pub heatmap_steps: u32,
+    /// The palette markers are drawn in for players who haven't picked their own.
+    #[serde(default)]
+    pub marker_palette: MarkerPalette,
+    #[serde(default)]
+    pub marker_style: MarkerStyle,
+    /// The brick asset of corner and edge markers, and the material of markers that glow.
+    #[serde(default = "default_marker_asset")]
+    pub marker_asset: String,
+    #[serde(default = "default_marker_material")]
+    pub marker_material: String,
+    /// How many seconds markers stay for before they are cleared automatically, or 0 to keep them
+    /// until they are cleared by hand.
+    #[serde(default)]
+    pub marker_clear_seconds: u64,
+    /// The id and name of the owner markers are placed under. Clearing markers clears every
+    /// brick of this owner, so it must not be shared with players or other plugins.
+    #[serde(default = "default_marker_owner_id")]
+    pub marker_owner_id: String,
+    #[serde(default = "default_marker_owner_name")]
+    pub marker_owner_name: String,
+    /// How many chunks' markers are loaded at once, or 0 to load every marker at once, and how
+    /// many milliseconds to wait between loads so the server doesn't hitch.
+    #[serde(default = "default_marker_batch_chunks")]
+    pub marker_batch_chunks: usize,
+    #[serde(default = "default_marker_batch_delay_ms")]
+    pub marker_batch_delay_ms: u64,
+    /// How many chunks can be marked at once before the marking has to be confirmed, or 0 to
+    /// never ask.
+    #[serde(default = "default_marker_confirm_chunks")]
+    pub marker_confirm_chunks: usize,
+    /// Whether players, weapons, and vehicles collide with corner and edge markers and minimaps.
+    #[serde(default)]
+    pub marker_collision: bool,
+    /// Whether clicking a marker tells the player the stats of its chunk.
+    #[serde(default = "default_marker_interact")]
+    pub marker_interact: bool,
+    /// The material and opacity (0 to 255) of the bricks filling chunks with the fill style.
+    #[serde(default = "default_marker_fill_material")]
+    pub marker_fill_material: String,
+    #[serde(default = "default_marker_fill_alpha")]
+    pub marker_fill_alpha: u8,
+    /// How big corner and edge markers are, in units from their center to their sides. When
+    /// `marker_size_max` is larger, markers grow towards it as their chunk nears the collider or
+    /// component limit.
+    #[serde(default = "default_marker_size")]
+    pub marker_size: u32,
+    #[serde(default)]
+    pub marker_size_max: u32,
+    /// The height, in world units, that `/chunks markfloor` lays its tiles at by default.
+    #[serde(default)]
+    pub marker_floor_height: i32,
+    /// The brick density, in bricks per cubic stud, at which density markers are fully red.
+    #[serde(default = "default_marker_density_full")]
+    pub marker_density_full: f64,
+}
+
+impl Default for Config {
+    /// Every option at its default, for running outside of omegga.
+    fn default() -> Self {
+        serde_json::from_str("{}").expect("every config option has a default")
+    }
+}
+
+impl Config {
+    /// Parses `collider_overrides` into a map of asset names to collider counts. Malformed entries
+    /// are skipped.
+    pub fn collider_override_map(&self) -> HashMap<String, u32> {
+        self.collider_overrides
+            .iter()
+            .filter_map(|entry| {
+                let (asset, count) = entry.split_once('=')?;
+                Some((asset.trim().to_owned(), count.trim().parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Parses `asset_class_costs` into a map of lowercase class names to per-stud and per-plate
+    /// costs. Malformed entries are skipped.
+    pub fn asset_class_cost_map(&self) -> HashMap<String, (f64, f64)> {
+        self.asset_class_costs
+            .iter()
+            .filter_map(|entry| {
+                let (class, costs) = entry.split_once('=')?;
+                let (stud, plate) = costs.split_once(',')?;
+                Some((
+                    class.trim().to_lowercase(),
+                    (stud.trim().parse().ok()?, plate.trim().parse().ok()?),
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns how long markers stay for before they are cleared automatically, if they are.
+    pub fn marker_timeout(&self) -> Option<Duration> {
+        (self.marker_clear_seconds > 0).then(|| Duration::from_secs(self.marker_clear_seconds))
+    }
+
+    /// Parses `marker_colors` into a map of lowercase state names to RGB colors. Malformed entries
+    /// are skipped.
+    pub fn marker_color_map(&self) -> HashMap<String, (u8, u8, u8)> {
+        self.marker_colors
+            .iter()
+            .filter_map(|entry| {
+                let (state, hex) = entry.split_once('=')?;
+                let hex = hex.trim().trim_start_matches('#');
+                if hex.len() != 6 {
+                    return None;
+                }
+                let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                Some((state.trim().to_lowercase(), (channel(0)?, channel(2)?, channel(4)?)))
+            })
+            .collect()
+    }
+
+    /// Parses `grid_presets` into a map of preset names to chunk sizes. Malformed entries and
+    /// sizes below 1 are skipped.
+    pub fn grid_preset_map(&self) -> HashMap<String, (i32, i32, i32)> {
+        self.grid_presets
+            .iter()
+            .filter_map(|entry| {
+                let (name, size) = entry.split_once('=')?;
+                let size = size
+                    .split('x')
+                    .map(|n| n.trim().parse().ok().filter(|n| *n > 0))
+                    .collect::<Option<Vec<i32>>>()?;
+                let size = match size[..] {
+                    [n] => (n, n, n),
+                    [x, y, z] => (x, y, z),
+                    _ => return None,
+                };
+                Some((name.trim().to_lowercase(), size))
+            })
+            .collect()
+    }
+}
+
+fn default_save_timeout() -> u64 {
+    30
+}
+
+fn default_chunk_size() -> i32 {
+    CHUNK_SIZE
+}
+
+fn default_world_bound() -> i32 {
+    1_000_000
+}
+
+fn default_stale_analysis_minutes() -> u64 {
+    60
+}
+
+fn default_autosave_pattern() -> String {
+    "autosave".to_owned()
+}
+
+fn default_unknown_collider_cost() -> u32 {
+    1
+}
+
+fn default_wire_limit() -> u32 {
+    50
+}
+
+fn default_light_limit() -> u32 {
+    20
+}
+
+fn default_audio_limit() -> u32 {
+    10
+}
+
+fn default_spawner_limit() -> u32 {
+    10
+}
+
+fn default_interact_limit() -> u32 {
+    30
+}
+
+fn default_emitter_limit() -> u32 {
+    10
+}
+
+fn default_glow_limit() -> u32 {
+    500
+}
+
+fn default_payload_limit() -> u32 {
+    8192
+}
+
+fn default_load_weight() -> f64 {
+    1.0
+}
+
+fn default_load_weight_colliders() -> f64 {
+    3.0
+}
+
+fn default_load_weight_components() -> f64 {
+    3.0
+}
+
+fn default_server_collider_budget() -> u64 {
+    10_000_000
+}
+
+fn default_server_component_budget() -> u64 {
+    25_000
+}
+
+fn default_load_brick_budget() -> u32 {
+    20000
+}
+
+fn default_marker_density_full() -> f64 {
+    10.0
+}
+
+fn default_heavy_chunk_percent() -> f64 {
+    50.0
+}
+
+fn default_heatmap_steps() -> u32 {
+    8
+}
+
+fn default_marker_size() -> u32 {
+    1
+}
+
+fn default_marker_owner_id() -> String {
+    MARKER_OWNER_UUID.to_owned()
+}
+
+fn default_marker_owner_name() -> String {
+    "Chunk Marker".to_owned()
+}
+
+fn default_marker_batch_chunks() -> usize {
+    250
+}
+
+fn default_marker_batch_delay_ms() -> u64 {
+    250
+}
+
+fn default_marker_confirm_chunks() -> usize {
+    2000
+}
+
+fn default_marker_interact() -> bool {
+    true
+}
+
+fn default_marker_asset() -> String {
+    "PB_DefaultMicroBrick".to_owned()
+}
+
+fn default_marker_material() -> String {
+    "BMC_Glow".to_owned()
+}
+
+fn default_marker_fill_material() -> String {
+    "BMC_Glass".to_owned()
+}
+
+fn default_marker_fill_alpha() -> u8 {
+    64
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use brickadia::{save::SaveData, write::SaveWriter};
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+use crate::{
+    analysis::AnalyzedSave,
+    config::Config,
+    marker::Palette,
+    metric::{load_score, Metric},
+};
+
+/// The directory exported analyses are written to, next to the plugin.
+pub const EXPORT_DIR: &str = "exports";
+
+/// The most columns of chunks a PNG export can be wide or tall.
+const PNG_COLUMNS_MAX: i32 = 4096;
+
+/// How many pixels wide and tall PNG exports try to be at least, by drawing each column of chunks
+/// as a square of pixels.
+const PNG_SIZE_MIN: i32 = 512;
+
+/// The color of columns without bricks in PNG exports.
+const PNG_EMPTY: [u8; 3] = [32, 32, 32];
+
+/// Returns the path to export the analysis `label` to with the file extension `extension`. The
+/// file is named after the label and the time of the analysis, so exports don't overwrite each
+/// other.
+fn export_path(save: &AnalyzedSave, label: &str, extension: &str) -> Result<PathBuf> {
+    fs::create_dir_all(EXPORT_DIR)?;
+    let label = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect::<String>();
+    Ok(PathBuf::from(EXPORT_DIR).join(format!("{}-{}.{}", label, save.analyzed_at, extension)))
+}
+
+/// Writes the whole analysis as JSON, in the same format as the command line's `--json`.
+/// Returns the path of the file written.
+pub fn export_json(save: &AnalyzedSave, label: &str) -> Result<PathBuf> {
+    let path = export_path(save, label, "json")?;
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), save)?;
+    Ok(path)
+}
+
+/// Writes the analysis as CSV, with a row for every chunk holding its coordinates, every metric,
+/// its estimated save size in bytes, and its load score. Returns the path of the file written.
+pub fn export_csv(save: &AnalyzedSave, config: &Config, label: &str) -> Result<PathBuf> {
+    let path = export_path(save, label, "csv")?;
+    let mut file = BufWriter::new(File::create(&path)?);
+
+    let metrics = Metric::ALL.iter().map(|m| m.name()).collect::<Vec<_>>();
+    writeln!(file, "x,y,z,{},save_bytes,score", metrics.join(","))?;
+
+    let mut chunks = save.chunks.iter().collect::<Vec<_>>();
+    chunks.sort_by_key(|(pos, _)| **pos);
+    for (pos, stats) in chunks {
+        let values = Metric::ALL
+            .iter()
+            .map(|m| m.value(stats).to_string())
+            .collect::<Vec<_>>();
+        writeln!(
+            file,
+            "{},{},{},{},{},{:.1}",
+            pos.0,
+            pos.1,
+            pos.2,
+            values.join(","),
+            stats.save_bytes(),
+            load_score(stats, config),
+        )?;
+    }
+    file.flush()?;
+    Ok(path)
+}
+
+/// Draws the analysis from above as a PNG, with a square for every column of chunks colored by
+/// the highest load score in it, or by the load score of the chunks at the height `layer` only.
+/// Returns the path of the file written.
+pub fn export_png(
+    save: &AnalyzedSave,
+    config: &Config,
+    palette: &Palette,
+    label: &str,
+    layer: Option<i32>,
+) -> Result<PathBuf> {
+    let mut columns = HashMap::<(i32, i32), f64>::new();
+    for (pos, stats) in save.chunks.iter() {
+        if layer.is_none_or(|z| pos.2 == z) {
+            let score = columns.entry((pos.0, pos.1)).or_insert(0.0);
+            *score = score.max(load_score(stats, config));
+        }
+    }
+    if columns.is_empty() {
+        bail!("there are no chunks with bricks to draw");
+    }
+
+    let (min_x, max_x) = columns.keys().fold((i32::MAX, i32::MIN), |(lo, hi), c| (lo.min(c.0), hi.max(c.0)));
+    let (min_y, max_y) = columns.keys().fold((i32::MAX, i32::MIN), |(lo, hi), c| (lo.min(c.1), hi.max(c.1)));
+    let (columns_x, columns_y) = (max_x - min_x + 1, max_y - min_y + 1);
+    if columns_x > PNG_COLUMNS_MAX || columns_y > PNG_COLUMNS_MAX {
+        bail!("the build is {}x{} chunks, more than the {} that can be drawn", columns_x, columns_y, PNG_COLUMNS_MAX);
+    }
+    let cell = (PNG_SIZE_MIN / columns_x.max(columns_y)).max(1) as usize;
+    let (width, height) = (columns_x as usize * cell, columns_y as usize * cell);
+
+    let mut pixels = vec![0; width * height * 3];
+    for (y, row) in pixels.chunks_mut(width * 3).enumerate() {
+        for (x, pixel) in row.chunks_mut(3).enumerate() {
+            let column = (min_x + (x / cell) as i32, min_y + (y / cell) as i32);
+            let rgb = match columns.get(&column) {
+                Some(&score) => {
+                    let color = palette.scale_color(score);
+                    [color.r, color.g, color.b]
+                }
+                None => PNG_EMPTY,
+            };
+            pixel.copy_from_slice(&rgb);
+        }
+    }
+
+    let label = match layer {
+        Some(z) => format!("{}-layer{}", label, z),
+        None => label.to_owned(),
+    };
+    let path = export_path(save, &label, "png")?;
+    write_png(&path, width as u32, height as u32, &pixels)?;
+    Ok(path)
+}
+
+/// Writes `pixels`, rows of RGB bytes, as a PNG image.
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+    // every row starts with the filter it uses, which is none
+    let mut image = ZlibEncoder::new(vec![], Compression::default());
+    for row in pixels.chunks(width as usize * 3) {
+        image.write_all(&[0])?;
+        image.write_all(row)?;
+    }
+    let image = image.finish()?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit RGB, with the only compression and filtering methods, and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"\x89PNG\r\n\x1a\n")?;
+    for (kind, data) in [(b"IHDR", &header[..]), (b"IDAT", &image[..]), (b"IEND", &[][..])].iter() {
+        let mut crc = Crc::new();
+        crc.update(*kind);
+        crc.update(data);
+        file.write_all(&(data.len() as u32).to_be_bytes())?;
+        file.write_all(*kind)?;
+        file.write_all(data)?;
+        file.write_all(&crc.sum().to_be_bytes())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes markers to the save `name` in the folder `dir` instead of loading them, so they can be
+/// looked over or loaded later. Never overwrites a save that is already there. Returns the path of
+/// the file written.
+pub fn export_markers(dir: &Path, name: &str, mut data: SaveData) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("markers can only be written to saves directly in the builds folder");
+    }
+    let path = dir.join(format!("{}.brs", name.trim_end_matches(".brs")));
+    let file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            bail!("there is already a save named {}, pick another name", name.trim_end_matches(".brs"))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    data.header1.brick_count = data.bricks.len() as u32;
+    let mut file = BufWriter::new(file);
+    let written = SaveWriter::new(&mut file, data)
+        .write()
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Ok(file.flush()?));
+    if let Err(e) = written {
+        // a partly written save would keep the name taken
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(path)
+}
+
+/// Reads an analysis exported as JSON from the file `name` in the export directory, with or
+/// without its extension.
+pub fn import_json(name: &str) -> Result<AnalyzedSave> {
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("only files in the {} folder can be imported", EXPORT_DIR);
+    }
+    let mut path = PathBuf::from(EXPORT_DIR).join(name);
+    if path.extension().is_none() {
+        path.set_extension("json");
+    }
+    let mut save: AnalyzedSave = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    save.imported = true;
+    Ok(save)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use brickadia::save::{Brick, Size};
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    #[test]
+    fn export_markers_never_overwrites_a_save() {
+        let dir = std::env::temp_dir().join(format!("omegga-chunks-markers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = export_markers(&dir, "markers.brs", SaveData::default()).unwrap();
+        assert_eq!(path, dir.join("markers.brs"));
+        let written = fs::read(&path).unwrap();
+        assert!(export_markers(&dir, "markers", SaveData::default()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), written);
+
+        assert!(export_markers(&dir, "../markers", SaveData::default()).is_err());
+        assert!(export_markers(&dir, ".markers", SaveData::default()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+
+    /// Returns an analysis of a few bricks spread over three chunks.
+    fn analysis() -> AnalyzedSave {
+        let mut data = SaveData::default();
+        data.header2.brick_assets = vec!["PB_DefaultBrick".to_owned()];
+        for &position in [(512, 512, 512), (600, 512, 512), (1536, 512, 512), (-512, 512, 1536)].iter() {
+            data.bricks.push(Brick {
+                position,
+                size: Size::Procedural(5, 5, 6),
+                ..Default::default()
+            });
+        }
+        AnalyzedSave::from_save(
+            data,
+            &Config::default(),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+        )
+    }
+
+    #[test]
+    fn export_json_writes_the_whole_analysis() {
+        let save = analysis();
+        let path = export_json(&save, "test json/export").unwrap();
+        assert_eq!(path, Path::new(EXPORT_DIR).join(format!("test_json_export-{}.json", save.analyzed_at)));
+        let written: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, serde_json::to_value(&save).unwrap());
+    }
+
+
+    #[test]
+    fn export_csv_writes_a_row_per_chunk() {
+        let save = analysis();
+        let config = Config::default();
+        let path = export_csv(&save, &config, "test-csv").unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "x,y,z,bricks,colliders,components,wires,lights,audio,spawners,interacts,emitters,glow,payload,density,save_bytes,score"
+        );
+        assert_eq!(lines.len(), 1 + save.chunks.len());
+        let columns = lines[0].split(',').count();
+        assert!(lines.iter().all(|line| line.split(',').count() == columns));
+
+        // rows are sorted by chunk position, with each chunk's bricks after it
+        assert!(lines[1].starts_with("-1,0,1,1,"));
+        assert!(lines[2].starts_with("0,0,0,2,"));
+        assert!(lines[3].starts_with("1,0,0,1,"));
+    }
+
+
+    #[test]
+    fn import_json_reads_back_an_export() {
+        let save = analysis();
+        let path = export_json(&save, "test-import").unwrap();
+        let name = path.file_stem().unwrap().to_str().unwrap().to_owned();
+        let imported = import_json(&name);
+        fs::remove_file(&path).unwrap();
+
+        let imported = imported.unwrap();
+        assert!(imported.imported && !save.imported);
+        assert_eq!(imported.chunks.len(), save.chunks.len());
+        for (pos, stats) in save.chunks.iter() {
+            assert_eq!(serde_json::to_value(&imported.chunks[pos]).unwrap(), serde_json::to_value(stats).unwrap());
+        }
+        assert_eq!(imported.brick_count, save.brick_count);
+
+        assert!(import_json("../Cargo.toml").is_err());
+        assert!(import_json(".hidden").is_err());
+    }
+
+    #[test]
+    fn export_png_draws_a_square_per_column() {
+        let save = analysis();
+        let config = Config::default();
+        let palette = Palette::new(config.marker_palette, &config);
+        let path = export_png(&save, &config, &palette, "test-png", Some(0)).unwrap();
+        assert!(path.ends_with(format!("test-png-layer0-{}.png", save.analyzed_at)));
+        let png = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        // two columns of chunks on the layer, drawn 256 pixels wide each
+        assert_eq!(&png[16..24], &[0, 0, 2, 0, 0, 0, 1, 0]);
+
+        let idat = 8 + 12 + 13;
+        let length = u32::from_be_bytes([png[idat], png[idat + 1], png[idat + 2], png[idat + 3]]) as usize;
+        assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+        let mut pixels = vec![];
+        ZlibDecoder::new(&png[idat + 8..idat + 8 + length]).read_to_end(&mut pixels).unwrap();
+        assert_eq!(pixels.len(), 256 * (1 + 512 * 3));
+        let color = palette.scale_color(load_score(&save.chunks[&(0, 0, 0)], &config));
+        assert_eq!(&pixels[..4], &[0, color.r, color.g, color.b]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        assert!(export_png(&save, &config, &palette, "test-png", Some(5)).is_err());
+    }
+}
@@ -1,345 +1,2678 @@
-use std::{collections::HashMap, fs::File, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::{anyhow, Result};
-use brickadia::{
-    read::SaveReader,
-    save::{Brick, BrickColor, BrickOwner, Color, Header2, SaveData, Size},
-};
-use lazy_static::lazy_static;
+use brickadia::{read::SaveReader, save::SaveData};
 use omegga::{Omegga, events::Event};
-use serde::{Deserialize, Serialize};
 use serde_json::{json};
-use tokio::{sync::RwLock, time::sleep};
-
-const SAVE_NAME: &'static str = "_omegga_chunks";
-const MARKER_OWNER_UUID: &'static str = "00000000-0000-0000-0000-000000000001";
-const CHUNK_SIZE: i32 = 1024;
-const COLLIDER_LIMIT: u32 = 65000;
-const COMPONENT_LIMIT: u32 = 75;
-const MARKER_COLORS: [BrickColor; 5] = [
-    BrickColor::Unique(Color {
-        r: 255,
-        g: 255,
-        b: 255,
-        a: 255,
-    }),
-    BrickColor::Unique(Color {
-        r: 0,
-        g: 255,
-        b: 0,
-        a: 255,
-    }),
-    BrickColor::Unique(Color {
-        r: 255,
-        g: 0,
-        b: 0,
-        a: 255,
-    }),
-    BrickColor::Unique(Color {
-        r: 0,
-        g: 0,
-        b: 255,
-        a: 255,
-    }),
-      BrickColor::Unique(Color {
-        r: 255,
-        g: 0,
-        b: 255,
-        a: 255,
-    })
-];
+use tokio::{
+    sync::{Mutex, RwLock},
+    task,
+    time::{self, sleep},
+};
 
-#[rustfmt::skip]
-const CHUNK_CORNERS: [(i32, i32, i32); 8] = [
-    (-CHUNK_SIZE / 2 + 1, -CHUNK_SIZE / 2 + 1, -CHUNK_SIZE / 2 + 1),
-    ( CHUNK_SIZE / 2 - 1, -CHUNK_SIZE / 2 + 1, -CHUNK_SIZE / 2 + 1),
-    (-CHUNK_SIZE / 2 + 1,  CHUNK_SIZE / 2 - 1, -CHUNK_SIZE / 2 + 1),
-    ( CHUNK_SIZE / 2 - 1,  CHUNK_SIZE / 2 - 1, -CHUNK_SIZE / 2 + 1),
-    (-CHUNK_SIZE / 2 + 1, -CHUNK_SIZE / 2 + 1,  CHUNK_SIZE / 2 - 1),
-    ( CHUNK_SIZE / 2 - 1, -CHUNK_SIZE / 2 + 1,  CHUNK_SIZE / 2 - 1),
-    (-CHUNK_SIZE / 2 + 1,  CHUNK_SIZE / 2 - 1,  CHUNK_SIZE / 2 - 1),
-    ( CHUNK_SIZE / 2 - 1,  CHUNK_SIZE / 2 - 1,  CHUNK_SIZE / 2 - 1),
-];
+mod analysis;
+mod args;
+mod chunk;
+mod cli;
+mod config;
+mod export;
+mod marker;
+mod metric;
+mod stream;
 
-pub fn pos_to_chunk(pos: (i32, i32, i32)) -> (i32, i32, i32) {
-    fn round(n: i32) -> i32 {
-        (n as f64 / CHUNK_SIZE as f64).floor() as i32
-    }
+use args::{find_subcommand, has_flag, Args, SUBCOMMANDS};
+use analysis::{reload_colliders, unix_now, AnalyzedSave, BrickFilter, ChunkStats, Progress, COLLIDER_LIMIT, COMPONENT_LIMIT};
+use chunk::{for_each_chunk, ChunkPos, Grid};
+use config::{Config, MarkerColoring, MarkerPalette, MarkerStyle};
+use export::{export_csv, export_json, export_markers, export_png, import_json};
+use marker::{blink_owner, mark_blink, marker_batches, marker_legend, marker_owner, mark_chunks, mark_floor, mark_minimap, mark_outline, outline_owner, mark_owners, Heatmap, MarkBy, Marking, MarkingKind, OwnerLegend, Palette, PlacedMarkers};
+use metric::{health_rating, health_score, load_score, severity, Metric};
+use stream::SaveStream;
+
+const SAVE_NAME: &str = "_omegga_chunks";
+const COMMAND_USAGE: &str = "Usage: <code>/chunks <subcommand> [arguments]</>. List the subcommands with <code>/chunks help</>.";
+/// The answer to every subcommand that needs an analysis before there is one.
+const NOT_ANALYZED: &str = "<color=\"a00\">The save has not been analyzed! Analyze it first with <code>/chunks analyze</>.</>";
+const STORE_KEY: &str = "analysis";
+const BASELINE_STORE_KEY: &str = "baseline";
+const PALETTES_STORE_KEY: &str = "palettes";
+const LEGENDS_STORE_KEY: &str = "hidden-legends";
+const SAVE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+const BLAME_COUNT: usize = 5;
+const ASSETS_COUNT: usize = 5;
+const TOP_COUNT: usize = 5;
+const TOP_MAX: usize = 100;
+const DIFF_COUNT: usize = 5;
+const MARK_RADIUS_MAX: i32 = 5;
+const PAGE_LINES: usize = 8;
+const OWNER_SHARE_PERCENT: f64 = 50.0;
+const MARK_REGION_MAX: i64 = 4096;
+const MINIMAP_SCALE_MAX: i32 = 50;
+/// How far from the player minimaps are built, in world units, so they aren't built on top of
+/// them.
+const MINIMAP_GAP: i32 = 60;
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How many times in a row a followed player's position can't be found before following stops,
+/// like when they left the server.
+const FOLLOW_MAX_MISSES: u32 = 60;
+const BLINK_INTERVAL: Duration = Duration::from_secs(1);
+const AUTO_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const AUTO_DEFAULT_MINUTES: u64 = 30;
+const AUTOSAVE_POLL_INTERVAL: Duration = Duration::from_secs(30);
 
-    (round(pos.0), round(pos.1), round(pos.2))
+/// Identifies what an analysis was run on, so an identical one can be skipped.
+#[derive(PartialEq, Eq)]
+struct SaveFingerprint {
+    /// The save's `SaveStream::content_hash`.
+    hash: u64,
+    grid: Grid,
+    filter: BrickFilter,
 }
 
-pub fn chunk_center(pos: (i32, i32, i32)) -> (i32, i32, i32) {
-    (
-        CHUNK_SIZE / 2 + pos.0 * CHUNK_SIZE,
-        CHUNK_SIZE / 2 + pos.1 * CHUNK_SIZE,
-        CHUNK_SIZE / 2 + pos.2 * CHUNK_SIZE,
-    )
+/// Holds an analysis that commands can share without cloning its chunk map.
+type SaveSlot = RwLock<Option<Arc<AnalyzedSave>>>;
+
+/// What to analyze and where to keep the result.
+#[derive(Default)]
+struct AnalysisRequest {
+    /// The existing save to analyze instead of saving the world.
+    save: Option<String>,
+    /// The name to keep the analysis under, besides making it the latest analysis.
+    name: Option<String>,
+    /// Which of the save's bricks to analyze.
+    filter: BrickFilter,
+    /// Whether to only reanalyze the chunks in the filter's region, merging them into the latest
+    /// analysis.
+    update: bool,
 }
 
-pub fn chunk_corner(i: usize, center: (i32, i32, i32)) -> (i32, i32, i32) {
-    (
-        center.0 + CHUNK_CORNERS[i].0,
-        center.1 + CHUNK_CORNERS[i].1,
-        center.2 + CHUNK_CORNERS[i].2,
-    )
+/// Parses the arguments of `/chunks analyze`, like `MyBuild owner Someone as before-event`, where
+/// the save is one from the server's builds to analyze instead of the world.
+fn parse_analysis_request(args: &[String], grid: &Grid) -> Result<AnalysisRequest, String> {
+    const USAGE: &str = "Usage: <code>/chunks analyze [save] [update] [owner name] [region x y z x y z] [chunks x y z x y z] [as name]</>";
+
+    let mut request = AnalysisRequest::default();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "as" => match rest.next() {
+                Some(name) if Metric::from_name(name).is_some() => {
+                    return Err(format!("<b>{}</> is a metric name, pick another name for the analysis!", name));
+                }
+                Some(name) => request.name = Some(name.clone()),
+                None => return Err(USAGE.to_owned()),
+            },
+            "owner" => match rest.next() {
+                Some(owner) => request.filter.owner = Some(owner.clone()),
+                None => return Err(USAGE.to_owned()),
+            },
+            "update" => request.update = true,
+            "region" | "chunks" => {
+                let coords = rest
+                    .by_ref()
+                    .take(6)
+                    .map(|n| n.parse::<i32>())
+                    .collect::<Result<Vec<_>, _>>();
+                let (a, b) = match coords.as_deref() {
+                    Ok(&[x1, y1, z1, x2, y2, z2]) => ((x1, y1, z1), (x2, y2, z2)),
+                    _ => return Err(USAGE.to_owned()),
+                };
+                if arg == "region" {
+                    request.filter.set_region(a, b);
+                } else {
+                    let min = (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2));
+                    let max = (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2));
+                    let (min, max) = grid.chunk_range_bounds(min, max).ok_or_else(|| USAGE.to_owned())?;
+                    request.filter.set_region(min, max);
+                }
+            }
+            save if request.save.is_none() => request.save = Some(save.to_owned()),
+            _ => return Err(USAGE.to_owned()),
+        }
+    }
+    if request.update && request.filter.region.is_none() {
+        return Err("Pass the <code>region</> or <code>chunks</> to reanalyze when updating an analysis!".to_owned());
+    }
+    Ok(request)
 }
 
-struct AnalyzedSave {
-    chunk_colliders: HashMap<(i32, i32, i32), (u32, u32, u32)>,
+/// Everything shared between commands and background tasks.
+#[derive(Default)]
+struct State {
+    config: RwLock<Option<Config>>,
+    /// The latest analysis.
+    analyzed_save: SaveSlot,
+    /// The analysis before the latest one, to diff against.
+    previous_save: SaveSlot,
+    /// The analysis pinned with `/chunks baseline set` to compare later analyses against.
+    baseline: SaveSlot,
+    /// Analyses saved under a name with `/chunks analyze as <name>`.
+    named_saves: RwLock<HashMap<String, Arc<AnalyzedSave>>>,
+    /// Held while an analysis runs, so two never run at once.
+    analysis_lock: Mutex<()>,
+    /// How often the save is analyzed automatically, if at all.
+    auto_interval: RwLock<Option<Duration>>,
+    /// The grid preset chosen with `/chunks grid`, if any.
+    grid_preset: RwLock<Option<String>>,
+    /// What the latest analysis was run on, if it was run since the plugin started and the
+    /// collider database hasn't been reloaded since.
+    last_fingerprint: RwLock<Option<SaveFingerprint>>,
+    /// Counts every marking, so a timed clear can tell if newer markers were placed since.
+    marker_generation: AtomicU64,
+    /// The markers currently in the world.
+    markers: RwLock<PlacedMarkers>,
+    /// The players whose chunk is outlined wherever they go, from `/chunks follow`, with when they
+    /// started so an outline turned off and on again isn't followed twice.
+    following: RwLock<HashMap<String, Instant>>,
+    /// The marker palettes players picked with `/chunks palette`, by player name.
+    palettes: RwLock<HashMap<String, MarkerPalette>>,
+    /// The players who turned off marker color legends with `/chunks legend off`.
+    hidden_legends: RwLock<HashSet<String>>,
+    /// The latest long result whispered to each player, by player name, to read the rest of with
+    /// `/chunks page`.
+    pages: RwLock<HashMap<String, Pages>>,
 }
 
-impl From<SaveData> for AnalyzedSave {
-    fn from(data: SaveData) -> Self {
-        lazy_static! {
-            static ref BRICK_COLLIDERS: HashMap<String, u32> =
-                serde_json::from_reader(File::open("colliders.json").unwrap()).unwrap();
-        }
+/// A result too long to whisper at once, split into pages of `PAGE_LINES` lines.
+struct Pages {
+    title: String,
+    lines: Vec<String>,
+    /// The page last whispered, from 1.
+    page: usize,
+}
+
+impl Pages {
+    fn count(&self) -> usize {
+        self.lines.len().div_ceil(PAGE_LINES).max(1)
+    }
 
-        let mut map = HashMap::new();
-        for brick in data.bricks.into_iter() {
-            let chunk_pos = pos_to_chunk(brick.position);
-            let collider_count = *BRICK_COLLIDERS
-                .get(data.header2.brick_assets[brick.asset_name_index as usize].as_str())
-                .unwrap_or(&1);
-            let component_count = brick.components.keys().len() as u32;
-            map.entry(chunk_pos)
-                .and_modify(|c: &mut (u32, u32, u32)| *c = (c.0 + 1, c.1 + collider_count, c.2 + component_count))
-                .or_insert((1, collider_count, component_count));
+    /// Whispers page `page` to `user` under the title, with how to read the next one.
+    fn whisper(&mut self, omegga: &Omegga, user: &str, page: usize) {
+        self.page = page;
+        omegga.whisper(user, self.title.clone());
+        for line in self.lines.iter().skip((page - 1) * PAGE_LINES).take(PAGE_LINES) {
+            omegga.whisper(user, line.clone());
         }
-        Self {
-            chunk_colliders: map,
+        if page < self.count() {
+            omegga.whisper(user, format!(
+                "Page {} of {}. Read the next with <code>/chunks page</>.",
+                page,
+                self.count(),
+            ));
+        } else if page > 1 {
+            omegga.whisper(user, format!("Page {} of {}.", page, self.count()));
         }
     }
 }
 
-pub fn mark_chunks(chunks: &[((i32, i32, i32), Option<(u32, u32, u32)>)]) -> SaveData {
-    let mut bricks = vec![];
+/// Whispers `lines` to `user` under `title` a page at a time, keeping them to read the other pages
+/// of with `/chunks page`.
+async fn whisper_pages(omegga: &Omegga, state: &State, user: &str, title: String, lines: Vec<String>) {
+    let mut pages = Pages { title, lines, page: 1 };
+    pages.whisper(omegga, user, 1);
+    state.pages.write().await.insert(user.to_owned(), pages);
+}
 
-    for (pos, opt) in chunks.iter() {
-        let center = chunk_center(*pos);
-        let col = match opt {
-            Some((_, colliders, components)) if *colliders > COLLIDER_LIMIT && *components > COMPONENT_LIMIT => 4,
-            Some((_, _colliders, components)) if *components > COMPONENT_LIMIT => 3,
-            Some((_, colliders, _components)) if *colliders > COLLIDER_LIMIT => 2,
-            _ => 0,
-        };
+/// Returns the grid analysis uses: the chosen preset, or the configured grid.
+async fn active_grid(state: &State, config: &Config) -> Grid {
+    state
+        .grid_preset
+        .read()
+        .await
+        .as_ref()
+        .and_then(|name| Grid::preset(config, name))
+        .unwrap_or_else(|| Grid::new(config))
+}
 
-        for i in 0..8 {
-            bricks.push(Brick {
-                owner_index: 1,
-                asset_name_index: 0,
-                material_index: if col > 0 { 0 } else { 1 },
-                material_intensity: 1,
-                color: MARKER_COLORS[col].clone(),
-                size: Size::Procedural(1, 1, 1),
-                position: chunk_corner(i, center),
-                ..Default::default()
-            })
-        }
-    }
-
-    SaveData {
-        header2: Header2 {
-            brick_assets: vec!["PB_DefaultMicroBrick".into()],
-            materials: vec!["BMC_Glow".into(),"BMC_Plastic".into()],
-            brick_owners: vec![BrickOwner {
-                id: MARKER_OWNER_UUID.parse().unwrap(),
-                name: "Chunk Marker".into(),
-                bricks: 0,
-            }],
-            ..Default::default()
-        },
-        bricks,
-        ..Default::default()
+/// Finds the chunk of `grid` that `user` is standing in.
+async fn player_chunk(omegga: &Omegga, user: &str, grid: &Grid) -> Result<ChunkPos> {
+    let pos = omegga
+        .get_player_position(user)
+        .await?
+        .ok_or(anyhow!("player has no position"))?;
+    Ok(grid.pos_to_chunk((pos.0 as i32, pos.1 as i32, pos.2 as i32)))
+}
+
+/// Returns the chat color for a count of `value` against `limit`.
+fn limit_color(value: u32, limit: u32) -> &'static str {
+    if value > limit {
+        "a00"
+    } else {
+        "0a0"
+    }
+}
+
+/// Formats `value` of a metric called `label`, colored by whether it exceeds `limit`.
+fn format_limited(label: &str, value: u32, limit: u32) -> String {
+    format!(
+        "<b><color=\"{}\">{} {}</></> (limit {})",
+        limit_color(value, limit),
+        value,
+        label,
+        limit
+    )
+}
+
+/// Formats component types and their counts as a comma separated list.
+fn format_component_types(types: &[(&str, u32)]) -> String {
+    types
+        .iter()
+        .map(|(name, count)| format!("<b>{}</> x{}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the `p`th percentile (0 to 100) of `sorted` using the nearest-rank method.
+fn percentile(sorted: &[u32], p: usize) -> u32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Joins analysis names for listing them, or says there are none.
+fn format_names<'a>(names: impl Iterator<Item = &'a String>) -> String {
+    let mut names = names.map(String::as_str).collect::<Vec<_>>();
+    if names.is_empty() {
+        return "none".to_owned();
     }
+    names.sort_unstable();
+    names.join(", ")
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct AuthUser {
-    name: String,
-    id: String,
+/// Returns the size and modification time of the file at `path`, if it exists.
+async fn file_stamp(path: &str) -> Option<(u64, SystemTime)> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some((meta.len(), meta.modified().ok()?))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Config {
-    authorized: Vec<AuthUser>,
+/// Waits for the server to finish writing the save `name`.
+///
+/// The save is considered written once its file differs from `previous` (the stamp of the
+/// file before saving, if there was one) and its size has stopped changing between two polls.
+/// Returns the save's path, or `None` if this doesn't happen within `timeout`.
+async fn wait_for_save(
+    omegga: &Omegga,
+    name: &str,
+    previous: Option<(u64, SystemTime)>,
+    timeout: Duration,
+) -> Result<Option<String>> {
+    let deadline = Instant::now() + timeout;
+    let mut last = None;
+
+    while Instant::now() < deadline {
+        sleep(SAVE_POLL_INTERVAL).await;
+
+        let path = match omegga.get_save_path(name).await? {
+            Some(path) => path,
+            None => continue,
+        };
+        let stamp = match file_stamp(&path).await {
+            Some(stamp) if Some(stamp) != previous => stamp,
+            _ => continue,
+        };
+
+        if last == Some(stamp.0) {
+            return Ok(Some(path));
+        }
+        last = Some(stamp.0);
+    }
+
+    Ok(None)
 }
 
 #[tokio::main]
 async fn main() {
+    // omegga starts the plugin without arguments, so any arguments mean we're used from the shell
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        match cli::run(&args) {
+            Ok(false) => return,
+            Ok(true) => process::exit(2),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     let omegga = Arc::new(Omegga::new());
     let mut rx = omegga.spawn();
 
-    let analyzed_save: Arc<RwLock<Option<AnalyzedSave>>> = Arc::new(RwLock::new(None));
-    let config: Arc<RwLock<Option<Config>>> = Arc::new(RwLock::new(None));
+    let state = Arc::new(State::default());
 
     while let Some(message) = rx.recv().await {
         match message {
             Event::Init { id, config: _config } =>
             {
-              let mut cfg = config.write().await;
-              *cfg = serde_json::from_value(_config).unwrap();
+              let cfg: Config = serde_json::from_value(_config).unwrap();
+              if marker_owner(&cfg).id.to_string() != cfg.marker_owner_id.to_lowercase() {
+                  omegga.error(format!(
+                      "marker-owner-id {} isn't a UUID, placing markers under {} instead",
+                      cfg.marker_owner_id,
+                      marker_owner(&cfg).id,
+                  ));
+              }
+              if cfg.auto_analysis_interval > 0 {
+                  *state.auto_interval.write().await = Some(Duration::from_secs(cfg.auto_analysis_interval * 60));
+              }
+              *state.config.write().await = Some(cfg);
               omegga.write_response(
                   id,
                   Some(json!({"registeredCommands": ["chunks"]})),
                   None,
               );
+
+              // bring back the analysis from before the last restart
+              let restore_omegga = omegga.clone();
+              let restore_state = state.clone();
+              tokio::spawn(async move {
+                  if let Err(e) = restore_analysis(&restore_omegga, STORE_KEY, &restore_state.analyzed_save).await {
+                      restore_omegga.error(format!("Failed to restore the analysis: {}", e));
+                  }
+                  if let Err(e) = restore_analysis(&restore_omegga, BASELINE_STORE_KEY, &restore_state.baseline).await {
+                      restore_omegga.error(format!("Failed to restore the baseline: {}", e));
+                  }
+                  if let Err(e) = restore_palettes(&restore_omegga, &restore_state).await {
+                      restore_omegga.error(format!("Failed to restore the marker palettes: {}", e));
+                  }
+                  if let Err(e) = restore_hidden_legends(&restore_omegga, &restore_state).await {
+                      restore_omegga.error(format!("Failed to restore who hid marker legends: {}", e));
+                  }
+              });
+
+              // a broken colliders.json doesn't stop the plugin, but the built-in database is used
+              // until it is fixed
+              match task::spawn_blocking(reload_colliders).await {
+                  Ok(Err(e)) => omegga.error(format!(
+                      "Failed to load colliders.json, using the built-in collider database until it is fixed and reloaded with /chunks reloadcolliders: {}",
+                      e,
+                  )),
+                  Err(e) => omegga.error(format!("Failed to load colliders.json: {}", e)),
+                  Ok(Ok(_)) => (),
+              }
+
+              tokio::spawn(auto_analysis(omegga.clone(), state.clone()));
+              tokio::spawn(autosave_analysis(omegga.clone(), state.clone()));
             }
             Event::Stop { id } => {
+              // keep the analysis around for the next time the plugin starts
+              if let Some(save) = &*state.analyzed_save.read().await {
+                  match serde_json::to_value(&**save) {
+                      Ok(value) => omegga.store_set(STORE_KEY, value),
+                      Err(e) => omegga.error(format!("Failed to store the analysis: {}", e)),
+                  }
+              }
               omegga.write_response(
                 id,
                 None,
                 None,
               );
             }
-            Event::Command { player, command, args } => {
-              if command == "chunks" {
+            Event::Command { player, command, args } if command == "chunks" => {
                 let omegga = omegga.clone();
-                let config = config.clone();
-                let analyzed_save = analyzed_save.clone();
-  
+                let state = state.clone();
+
                 tokio::spawn(async move {
-                    if let Err(e) =
-                        run_command(omegga.clone(), config, analyzed_save, player, args).await
-                    {
+                    if let Err(e) = run_command(omegga.clone(), state, player, args).await {
                         omegga.error(format!("An error occurred: {}", e));
                     }
                 });
-              }
             }
             _ => (),
         }
     }
 }
 
-async fn run_command(
-    omegga: Arc<Omegga>,
-    config: Arc<RwLock<Option<Config>>>,
-    analyzed_save: Arc<RwLock<Option<AnalyzedSave>>>,
-    user: String,
-    args: Vec<String>,
-) -> Result<()> {
-    let config_read = config.read().await;
-    let config = match &*config_read {
-        Some(cfg) => cfg,
-        None => return Ok(()),
-    };
+/// Analyzes the save every `State::auto_interval` while it is set.
+async fn auto_analysis(omegga: Arc<Omegga>, state: Arc<State>) {
+    let mut current = None;
+    let mut next = Instant::now();
+    loop {
+        sleep(AUTO_POLL_INTERVAL).await;
 
-    let command = &args[0];
+        // start counting from now whenever the interval is turned on or changed
+        let interval = *state.auto_interval.read().await;
+        if interval != current {
+            current = interval;
+            next = Instant::now() + interval.unwrap_or_default();
+            continue;
+        }
+        let interval = match interval {
+            Some(interval) if Instant::now() >= next => interval,
+            _ => continue,
+        };
+        next = Instant::now() + interval;
 
-    if !config
-        .authorized
-        .iter()
-        .any(|u| u.name.to_lowercase() == user.to_lowercase())
-    {
-        omegga.whisper(
-            user,
-            "<color=\"a00\">You are not authorized to use this command!</>",
-        );
-        return Ok(());
+        let config = match &*state.config.read().await {
+            Some(config) => config.clone(),
+            None => continue,
+        };
+        if let Err(e) = analyze(&omegga, &state, &config, None, AnalysisRequest::default()).await {
+            omegga.error(format!("Automatic analysis failed: {}", e));
+        }
     }
+}
 
-    match command.as_str() {
-        "analyze" => {
-            // save and get the save's path
-            if let Err(_) = omegga.save_bricks(SAVE_NAME).await {
-                omegga.whisper(user, "<color=\"a00\">Failed to save!</>");
-                return Ok(());
+/// Keeps the chunk `user` is in outlined while they are followed, moving the outline whenever they
+/// walk into another chunk.
+async fn follow_player(omegga: Arc<Omegga>, state: Arc<State>, user: String, started: Instant) {
+    let owner = outline_owner(&user).id.to_string();
+    let mut outlined = None;
+    let mut misses = 0;
+    while state.following.read().await.get(&user) == Some(&started) && misses < FOLLOW_MAX_MISSES {
+        sleep(FOLLOW_POLL_INTERVAL).await;
+
+        let config = match &*state.config.read().await {
+            Some(config) => config.clone(),
+            None => continue,
+        };
+        let save = state.analyzed_save.read().await.clone();
+        let grid = match &save {
+            Some(save) => save.grid,
+            None => active_grid(&state, &config).await,
+        };
+        let pos = match player_chunk(&omegga, &user, &grid).await {
+            Ok(pos) => pos,
+            Err(_) => {
+                misses += 1;
+                continue;
             }
-            sleep(Duration::from_millis(2500)).await;
-            let path = match omegga.get_save_path(SAVE_NAME).await {
-                Ok(Some(p)) => p,
-                _ => {
-                    omegga.whisper(user, "<color=\"a00\">Failed to find save! Try again.</>");
-                    return Ok(());
+        };
+        misses = 0;
+        if outlined == Some((pos, grid)) {
+            continue;
+        }
+
+        let stats = save.as_ref().and_then(|save| save.chunks.get(&pos));
+        let palette = user_palette(&state, &user, &config).await;
+        omegga.clear_bricks(owner.clone(), true);
+        if let Err(e) = omegga.load_save_data(mark_outline(pos, stats, &grid, &config, &palette, &user), true, (0, 0, 0)).await {
+            omegga.error(format!("Failed to outline the chunk of {}: {}", user, e));
+        }
+        outlined = Some((pos, grid));
+    }
+
+    let mut following = state.following.write().await;
+    if following.get(&user) == Some(&started) {
+        following.remove(&user);
+    }
+    // a newer follow clears the outline itself
+    if !following.contains_key(&user) {
+        omegga.clear_bricks(owner, true);
+    }
+}
+
+/// Analyzes the server's autosaves as they are written, instead of saving the world again.
+async fn autosave_analysis(omegga: Arc<Omegga>, state: Arc<State>) {
+    let mut seen = None;
+    let mut pending = None;
+    loop {
+        sleep(AUTOSAVE_POLL_INTERVAL).await;
+
+        let config = match &*state.config.read().await {
+            Some(config) if config.autosave_analysis => config.clone(),
+            _ => continue,
+        };
+
+        // find the newest autosave
+        let saves = match omegga.get_saves().await {
+            Ok(saves) => saves,
+            Err(e) => {
+                omegga.error(format!("Failed to list saves: {}", e));
+                continue;
+            }
+        };
+        let pattern = config.autosave_pattern.to_lowercase();
+        let mut newest = None;
+        for path in saves.into_iter().filter(|s| s.to_lowercase().contains(&pattern)) {
+            if let Some(stamp) = file_stamp(&path).await {
+                if newest.as_ref().is_none_or(|(_, (_, time))| stamp.1 > *time) {
+                    newest = Some((path, stamp));
                 }
-            };
+            }
+        }
+        let (path, stamp) = match newest {
+            Some(newest) => newest,
+            None => continue,
+        };
 
-            // read the save (we can't use tokio for this)
-            let data = SaveReader::new(File::open(path).unwrap())
-                .unwrap()
-                .read_all_skip_preview()
-                .unwrap();
+        // don't analyze whatever autosave was already there when the plugin started
+        if seen.is_none() {
+            seen = Some(stamp);
+            continue;
+        }
+        if seen == Some(stamp) {
+            continue;
+        }
 
-            // set the analyzed save
-            analyzed_save.write().await.replace(data.into());
+        // wait until the autosave stops changing, so it is fully written
+        if pending != Some(stamp) {
+            pending = Some(stamp);
+            continue;
+        }
 
-            omegga.whisper(user, "<color=\"0a0\">The save has been analyzed. Any subsequent changes must be reanalyzed.</>");
+        let _analyzing = match state.analysis_lock.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => continue,
+        };
+        seen = Some(stamp);
+        if let Err(e) = analyze_file(&omegga, &state, &config, None, AnalysisRequest::default(), path).await {
+            omegga.error(format!("Autosave analysis failed: {}", e));
         }
-        "in" => {
-            // find the chunk the current player is in
-            let pos = omegga
-                .get_player_position(user.clone())
-                .await?
-                .ok_or(anyhow!("player has no position"))?;
-            omegga.whisper(
-                user,
-                format!(
-                    "You are in chunk {:?}.",
-                    pos_to_chunk((pos.0 as i32, pos.1 as i32, pos.2 as i32))
-                ),
-            );
+    }
+}
+
+/// Sends `message` to `user`, or to the console for analyses nobody asked for.
+fn notify(omegga: &Omegga, user: Option<&str>, message: impl Into<String>) {
+    match user {
+        Some(user) => omegga.whisper(user, message),
+        None => omegga.log(message),
+    }
+}
+
+/// Saves the world and analyzes it, or analyzes the existing save the request names, replacing
+/// the latest analysis. Progress is reported to `user`, or only logged if the analysis was
+/// automatic.
+async fn analyze(
+    omegga: &Omegga,
+    state: &State,
+    config: &Config,
+    user: Option<&str>,
+    request: AnalysisRequest,
+) -> Result<()> {
+    let _analyzing = match state.analysis_lock.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => {
+            notify(omegga, user, "<color=\"a00\">An analysis is already running! Wait for it to finish first.</>");
+            return Ok(());
         }
-        "count" => {
-            // list the bricks/colliders in this chunk
-            match &*analyzed_save.read().await {
-                Some(save) => {
-                    let pos = omegga.get_player_position(user.clone()).await?.ok_or(anyhow!("player has no position"))?;
+    };
 
-                    let chunk_pos = pos_to_chunk((pos.0 as i32, pos.1 as i32, pos.2 as i32));
-                    if let Some((bricks, colliders, components)) = save.chunk_colliders.get(&chunk_pos) {
-                        omegga.whisper(user, format!(
-                            "There are <b>{} bricks</>, <b><color=\"{}\">{} colliders</></>, and <b>{} components</> in the chunk {:?}.",
-                            bricks,
-                            if *colliders > COLLIDER_LIMIT { "a00" } else { "0a0" },
-                            colliders,
-                            components,
-                            chunk_pos,
-                        ));
-                    } else {
-                        omegga.whisper(user, "<color=\"a00\">This chunk has no bricks or colliders!</>");
-                    }
-                }
-                None => omegga.whisper(user, "<color=\"a00\">The save has not been analyzed! Analyze it first with <code>/chunks analyze</>.</>"),
+    if let Some(save) = &request.save {
+        return match omegga.get_save_path(save).await? {
+            Some(path) => analyze_file(omegga, state, config, user, request, path).await,
+            None => {
+                notify(omegga, user, format!("<color=\"a00\">There is no save named <b>{}</>!</>", save));
+                Ok(())
             }
+        };
+    }
+
+    // remember what the previous save looked like so we can tell when the new one is written
+    let previous = match omegga.get_save_path(SAVE_NAME).await? {
+        Some(path) => file_stamp(&path).await,
+        None => None,
+    };
+
+    // save and wait for the save to be written
+    if omegga.save_bricks(SAVE_NAME).await.is_err() {
+        notify(omegga, user, "<color=\"a00\">Failed to save!</>");
+        return Ok(());
+    }
+    let timeout = Duration::from_secs(config.save_timeout);
+    let path = match wait_for_save(omegga, SAVE_NAME, previous, timeout).await? {
+        Some(p) => p,
+        None => {
+            notify(omegga, user, "<color=\"a00\">Failed to find save! Try again.</>");
+            return Ok(());
         }
-        "mark" => {
-            // mark the chunk we're currently in
-            match &*analyzed_save.read().await {
-                Some(save) => {
-                    let pos = omegga.get_player_position(user.clone()).await?.ok_or(anyhow!("player has no position"))?;
-                    let chunk_pos = pos_to_chunk((pos.0 as i32, pos.1 as i32, pos.2 as i32));
-                    let opt = save.chunk_colliders.get(&chunk_pos);
-                    let marker_data = mark_chunks(&vec![(chunk_pos, opt.copied())]);
-                    omegga.load_save_data(marker_data, true, (0, 0, 0)).await?;
-                    omegga.whisper(user, "<color=\"0a0\">Your chunk has been marked.</>");
+    };
+
+    analyze_file(omegga, state, config, user, request, path).await
+}
+
+/// Analyzes the save at `path`, replacing the latest analysis. The caller must hold
+/// `State::analysis_lock`.
+async fn analyze_file(
+    omegga: &Omegga,
+    state: &State,
+    config: &Config,
+    user: Option<&str>,
+    request: AnalysisRequest,
+    path: String,
+) -> Result<()> {
+    let AnalysisRequest { name, mut filter, update, .. } = request;
+    let grid = active_grid(state, config).await;
+
+    // updates reanalyze whole chunks, so they can replace the latest analysis's chunks
+    let base = match (update, filter.region) {
+        (true, Some(region)) => {
+            let base = match state.analyzed_save.read().await.clone() {
+                Some(base) if base.grid == grid && base.filter.region.is_none() && base.filter.owner == filter.owner => base,
+                Some(_) => {
+                    notify(omegga, user, "<color=\"a00\">Only a full analysis with the same grid and owner can be updated! Analyze the whole save first.</>");
+                    return Ok(());
+                }
+                None => {
+                    notify(omegga, user, NOT_ANALYZED);
+                    return Ok(());
                 }
-                None => omegga.whisper(user, "<color=\"a00\">The save has not been analyzed! Analyze it first with <code>/chunks analyze</>.</>"),
+            };
+            let (min, max) = grid.bounds_chunks(region);
+            filter.region = Some(grid.chunk_range_bounds_clamped(min, max));
+            Some((base, min, max))
+        }
+        _ => None,
+    };
+
+    // reuse the latest analysis if it was of the same save with the same settings
+    let hash_path = path.clone();
+    let fingerprint = task::spawn_blocking(move || -> Result<u64> {
+        SaveStream::open(BufReader::new(File::open(hash_path)?))?.content_hash()
+    })
+    .await?
+    .ok()
+    .map(|hash| SaveFingerprint {
+        hash,
+        grid,
+        filter: filter.clone(),
+    });
+    if fingerprint.is_some() && *state.last_fingerprint.read().await == fingerprint {
+        let current = state.analyzed_save.read().await.clone();
+        if let Some(current) = current {
+            // the analysis was just confirmed to be current, so it isn't stale anymore
+            let mut refreshed = (*current).clone();
+            refreshed.analyzed_at = unix_now();
+            let analyzed = Arc::new(refreshed);
+            *state.analyzed_save.write().await = Some(analyzed.clone());
+            if let Some(name) = &name {
+                state.named_saves.write().await.insert(name.clone(), analyzed);
             }
+            notify(omegga, user, "<color=\"0a0\">The save hasn't changed since the last analysis, so its results are still current.</>");
+            if let Some(name) = name {
+                notify(omegga, user, format!("The analysis was saved as <b>{}</>.", name));
+            }
+            return Ok(());
         }
-        "markall" => {
-            // mark the chunk we're currently in
-            match &*analyzed_save.read().await {
-                Some(save) => {
-                    let mut chunks = vec![];
-                    for (pos, opt) in save.chunk_colliders.iter() {
-                        chunks.push((*pos, Some(*opt)));
-                    }
-                    let marker_data = mark_chunks(&chunks);
-                    omegga.load_save_data(marker_data, true, (0, 0, 0)).await?;
-                    omegga.whisper(user, "<color=\"0a0\">All chunks have been marked.</>");
+    }
+
+    notify(omegga, user, "Analyzing the save...");
+
+    // read and analyze the save on the blocking pool so other commands and events
+    // keep being handled while a large save is parsed
+    let started = Instant::now();
+    let progress = Arc::new(Progress::default());
+    let analysis_config = config.clone();
+    let analysis_progress = progress.clone();
+    let mut analysis = task::spawn_blocking(move || -> Result<AnalyzedSave> {
+        let file = File::open(path)?;
+        if analysis_config.streaming_analysis {
+            AnalyzedSave::from_stream(BufReader::new(file), &analysis_config, &grid, &filter, &analysis_progress)
+        } else {
+            let data = SaveReader::new(file)?.read_all_skip_preview()?;
+            Ok(AnalyzedSave::from_save(data, &analysis_config, &grid, &filter, &analysis_progress))
+        }
+    });
+
+    // report progress every so often until the analysis finishes
+    let result = loop {
+        match time::timeout(PROGRESS_INTERVAL, &mut analysis).await {
+            Ok(result) => break result?,
+            Err(_) => {
+                let user = match user {
+                    Some(user) => user,
+                    None => continue,
+                };
+                let total = progress.total.load(Ordering::Relaxed);
+                let bricks = progress.bricks.load(Ordering::Relaxed);
+                let elapsed = started.elapsed().as_secs();
+                if total == 0 {
+                    omegga.whisper(user, format!("Reading the save... ({}s)", elapsed));
+                } else {
+                    omegga.whisper(user, format!(
+                        "Analyzed <b>{}</> of <b>{}</> bricks ({:.0}%, {}s)",
+                        bricks,
+                        total,
+                        bricks as f64 / total as f64 * 100.0,
+                        elapsed,
+                    ));
                 }
-                None => omegga.whisper(user, "<color=\"a00\">The save has not been analyzed! Analyze it first with <code>/chunks analyze</>.</>"),
             }
         }
-        "clear" => {
-            // clear chunk markers
-            omegga.clear_bricks(MARKER_OWNER_UUID, true);
-            omegga.whisper(user, "<color=\"0a0\">Chunk markers have been cleared.</>");
+    };
+    let mut analyzed = match result {
+        Ok(analyzed) => analyzed,
+        Err(e) => {
+            notify(omegga, user, "<color=\"a00\">Failed to read the save!</>");
+            return Err(e);
+        }
+    };
+
+    // warn about assets we don't know the collider count of
+    let unknown = analyzed.unknown_assets.len();
+    let filtered = analyzed.filter.describe();
+
+    // merge updated chunks into the analysis they update, whose other chunks may be out of date
+    // so it can't be reused as an analysis of this save
+    let fingerprint = match &base {
+        Some((base, min, max)) => {
+            analyzed = base.with_chunks_from(&analyzed, *min, *max);
+            None
         }
-        unknown => omegga.whisper(user, format!("Unknown subcommand {}.", unknown)),
+        None => fingerprint,
+    };
+
+    // set the analyzed save, keeping the last one around to diff against
+    let analyzed = Arc::new(analyzed);
+    let previous = state.analyzed_save.write().await.replace(analyzed.clone());
+    if previous.is_some() {
+        *state.previous_save.write().await = previous;
+    }
+    *state.last_fingerprint.write().await = fingerprint;
+    if let Some(name) = &name {
+        state.named_saves.write().await.insert(name.clone(), analyzed.clone());
+    }
+
+    notify(omegga, user, format!(
+        "<color=\"0a0\">The save has been analyzed in {:.1}s. Any subsequent changes must be reanalyzed.</>",
+        started.elapsed().as_secs_f64(),
+    ));
+    match (&base, filtered) {
+        (Some((_, min, max)), _) => notify(omegga, user, format!(
+            "Chunks {:?} to {:?} were reanalyzed and merged into the latest analysis.",
+            min,
+            max,
+        )),
+        (None, Some(filter)) => notify(omegga, user, format!("Only {} were analyzed.", filter)),
+        (None, None) => (),
+    }
+    if let Some(name) = name {
+        notify(omegga, user, format!("The analysis was saved as <b>{}</>.", name));
+    }
+    if refresh_markers(omegga, state, user, &analyzed, config).await? {
+        notify(omegga, user, "The chunk markers were redrawn with the new analysis.");
     }
+    if unknown > 0 {
+        notify(omegga, user, format!(
+            "<color=\"a00\"><b>{}</> assets are missing from the collider database and were counted as {} colliders each. List them with <code>/chunks unknowns</>.</>",
+            unknown,
+            config.unknown_collider_cost,
+        ));
+    }
+    Ok(())
+}
+
+/// Restores the analysis stored under `key`, if there is one and `analyzed_save` is still empty.
+/// It is marked as restored, since the save may have changed in between.
+async fn restore_analysis(omegga: &Omegga, key: &str, analyzed_save: &SaveSlot) -> Result<()> {
+    let value = match omegga.store_get(key).await? {
+        Some(value) if !value.is_null() => value,
+        _ => return Ok(()),
+    };
+    let mut save: AnalyzedSave = serde_json::from_value(value)?;
+    save.restored = true;
+
+    let mut slot = analyzed_save.write().await;
+    if slot.is_none() {
+        *slot = Some(Arc::new(save));
+    }
+    Ok(())
+}
+
+/// Restores the marker palettes players picked before the last restart.
+async fn restore_palettes(omegga: &Omegga, state: &State) -> Result<()> {
+    let value = match omegga.store_get(PALETTES_STORE_KEY).await? {
+        Some(value) if !value.is_null() => value,
+        _ => return Ok(()),
+    };
+    let palettes: HashMap<String, MarkerPalette> = serde_json::from_value(value)?;
+    state.palettes.write().await.extend(palettes);
+    Ok(())
+}
 
+/// Restores the players who hid marker legends before the last restart.
+async fn restore_hidden_legends(omegga: &Omegga, state: &State) -> Result<()> {
+    let value = match omegga.store_get(LEGENDS_STORE_KEY).await? {
+        Some(value) if !value.is_null() => value,
+        _ => return Ok(()),
+    };
+    let users: HashSet<String> = serde_json::from_value(value)?;
+    state.hidden_legends.write().await.extend(users);
     Ok(())
 }
+
+/// Returns the palette `user` sees markers in: the one they picked, or the configured one.
+async fn user_palette(state: &State, user: &str, config: &Config) -> Palette {
+    let palette = state.palettes.read().await.get(user).copied();
+    Palette::new(palette.unwrap_or(config.marker_palette), config)
+}
+
+/// Formats a number of seconds as a rough age, like `3h` or `2d`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Warns `user` when `save` was restored after a restart, imported, or is older than the
+/// configured `stale_analysis_minutes`, since its numbers may be out of date.
+fn warn_if_stale(omegga: &Omegga, user: &str, save: &AnalyzedSave, config: &Config) {
+    if save.imported {
+        omegga.whisper(user, "<color=\"aa0\">This analysis was imported from a file and may not match the current save.</>");
+    }
+    if save.restored {
+        omegga.whisper(user, "<color=\"aa0\">This analysis was restored after a restart and may be out of date. Reanalyze with <code>/chunks analyze</> for current numbers.</>");
+    }
+    let age = unix_now().saturating_sub(save.analyzed_at);
+    if config.stale_analysis_minutes > 0 && save.analyzed_at > 0 && age >= config.stale_analysis_minutes * 60 {
+        omegga.whisper(user, format!(
+            "<color=\"aa0\">This analysis is <b>{}</> old and may be out of date. Reanalyze with <code>/chunks analyze</> for current numbers.</>",
+            format_age(age),
+        ));
+    }
+}
+
+/// Whispers `user` how many chunks changed between `before` and `after` and which changed the
+/// most, comparing `metric`. `labels` describe the two analyses.
+fn whisper_diff(
+    omegga: &Omegga,
+    user: &str,
+    before: &AnalyzedSave,
+    after: &AnalyzedSave,
+    labels: (&str, &str),
+    metric: Metric,
+) {
+    let value = |save: &AnalyzedSave, pos| save.chunks.get(pos).map_or(0, |s| metric.value(s) as i64);
+    let mut changes = after
+        .chunks
+        .keys()
+        .chain(before.chunks.keys().filter(|pos| !after.chunks.contains_key(pos)))
+        .map(|pos| (pos, value(after, pos) - value(before, pos)))
+        .filter(|(_, change)| *change != 0)
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let total = changes.iter().map(|(_, change)| change).sum::<i64>();
+    omegga.whisper(user, format!(
+        "<b>{}</> chunks changed, with <b>{:+} {}</> overall from {} to {}.",
+        changes.len(),
+        total,
+        metric.label(),
+        labels.0,
+        labels.1,
+    ));
+
+    let gained = changes.iter().filter(|(_, change)| *change > 0).take(DIFF_COUNT);
+    let lost = changes.iter().rev().filter(|(_, change)| *change < 0).take(DIFF_COUNT);
+    for (title, color, chunks) in [("gained", "a00", gained.collect::<Vec<_>>()), ("lost", "0a0", lost.collect())] {
+        if chunks.is_empty() {
+            continue;
+        }
+        omegga.whisper(user, format!("Chunks that {} the most {}:", title, metric.label()));
+        for (pos, change) in chunks {
+            omegga.whisper(user, format!(
+                "{:?}: <b><color=\"{}\">{:+}</></> ({} now)",
+                pos,
+                color,
+                change,
+                value(after, pos),
+            ));
+        }
+    }
+}
+
+/// Returns how long markers should stay for from `for <seconds>` in `args`, if it is there.
+fn marker_timeout_arg(args: &[String]) -> Result<Option<Duration>, String> {
+    let args = Args::new(args, "Usage: <code>... for <seconds></>, with seconds above 0");
+    match args.after("for")?.map(str::parse::<u64>) {
+        Some(Ok(seconds)) if seconds > 0 => Ok(Some(Duration::from_secs(seconds))),
+        Some(_) => Err(args.usage()),
+        None => Ok(None),
+    }
+}
+
+/// Parses `by <metric>` in `args`, or `by score`, into what to color markers by, if it is there.
+fn mark_by_arg(args: &[String]) -> Result<Option<MarkBy>, String> {
+    let usage = format!("Usage: <code>... by <metric></>, with score or one of: {}", Metric::names());
+    let args = Args::new(args, &usage);
+    match args.after("by")? {
+        Some(name) => MarkBy::from_name(name).map(Some).ok_or(usage),
+        None => Ok(None),
+    }
+}
+
+/// Parses `--to <save name>` in `args` into the save to write markers to instead of placing them,
+/// if it is there.
+fn marker_save_arg(args: &[String]) -> Result<Option<String>, String> {
+    let args = Args::new(args, "Usage: <code>... --to <save name></>");
+    Ok(args.after("--to")?.map(str::to_owned))
+}
+
+/// Records that markers were just placed, and clears them after `timeout` unless more are placed
+/// before then.
+fn markers_placed(omegga: &Arc<Omegga>, state: &Arc<State>, user: &str, timeout: Option<Duration>) {
+    let generation = state.marker_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    omegga.whisper(user, format!("The markers will be cleared in {} seconds.", timeout.as_secs()));
+
+    let omegga = omegga.clone();
+    let state = state.clone();
+    tokio::spawn(async move {
+        sleep(timeout).await;
+        if state.marker_generation.load(Ordering::SeqCst) == generation {
+            if let Some(config) = &*state.config.read().await {
+                omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+            }
+            state.markers.write().await.clear();
+        }
+    });
+}
+
+/// Returns the marker style named in `args`, if any.
+fn marker_style_arg(args: &[String]) -> Option<MarkerStyle> {
+    args.iter().find_map(|arg| MarkerStyle::from_name(arg))
+}
+
+/// Marks `chunks` with the marking options in `args`: a marker style, `owners` to color them by
+/// owner, `by <metric>` to color them by a metric, `heatmap` to color them by how they rank in the
+/// whole analysis, and `for <seconds>` to clear them after a while.
+async fn place_markers(
+    omegga: &Arc<Omegga>,
+    state: &Arc<State>,
+    user: &str,
+    save: &AnalyzedSave,
+    config: &Config,
+    args: &[String],
+    chunks: &[(ChunkPos, Option<&ChunkStats>)],
+) -> Result<()> {
+    let timeout = match marker_timeout_arg(args) {
+        Ok(timeout) => timeout.or(config.marker_timeout()),
+        Err(usage) => {
+            omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+            return Ok(());
+        }
+    };
+    if let Err(usage) = mark_by_arg(args) {
+        omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+        return Ok(());
+    }
+    let save_to = match marker_save_arg(args) {
+        Ok(name) => name,
+        Err(usage) => {
+            omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+            return Ok(());
+        }
+    };
+
+    let space;
+    let chunks = if has_flag(args, "space") {
+        space = with_expansion_space(save, config, chunks);
+        &space[..]
+    } else {
+        chunks
+    };
+
+    let palette = user_palette(state, user, config).await;
+    let (marker_data, legend) = draw_markers(save, config, args, chunks, &palette);
+    // owner markers whisper their own legend right away
+    let (done, legend) = match legend {
+        Legend::Owners(legend) if legend.is_empty() => {
+            omegga.whisper(user, "<color=\"a00\">There are no bricks to mark!</>");
+            return Ok(());
+        }
+        Legend::Owners(legend) => {
+            let marked = chunks.iter().filter(|(_, stats)| stats.is_some()).count();
+            whisper_owner_legend(omegga, user, save, &legend, marked);
+            (None, vec![])
+        }
+        Legend::Colors(legend) => match chunks.len() {
+            1 => (Some("The chunk has been marked.".to_owned()), legend),
+            n => (Some(format!("{} chunks have been marked.", n)), legend),
+        },
+    };
+
+    let confirm = config.marker_confirm_chunks > 0 && chunks.len() > config.marker_confirm_chunks;
+    if has_flag(args, "--dry-run") || (confirm && !has_flag(args, "confirm")) {
+        omegga.whisper(user, format!(
+            "<color=\"aa0\">This would mark <b>{} chunks</> with <b>{} marker bricks</>.</>",
+            chunks.len(),
+            marker_data.bricks.len(),
+        ));
+        if !has_flag(args, "--dry-run") {
+            omegga.whisper(user, "Add <code>confirm</> to the command to place them anyway.");
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = save_to {
+        let dir = match builds_dir(omegga).await? {
+            Some(dir) => dir,
+            None => {
+                omegga.whisper(user, "<color=\"a00\">Could not find the builds folder to write the markers to!</>");
+                return Ok(());
+            }
+        };
+        match task::spawn_blocking(move || export_markers(&dir, &name, marker_data)).await? {
+            Ok(path) => omegga.whisper(user, format!(
+                "<color=\"0a0\">The markers were written to <b>{}</> instead of being placed.</>",
+                path.display(),
+            )),
+            Err(e) => omegga.whisper(user, format!("<color=\"a00\">Failed to write the markers: {}</>", e)),
+        }
+        return Ok(());
+    }
+
+    let marking = Marking {
+        user: user.to_owned(),
+        args: args.to_vec(),
+        kind: MarkingKind::Chunks,
+    };
+    place_marker_data(omegga, state, user, marker_data, &save.grid, config, &marking).await?;
+    if let Some(done) = done {
+        omegga.whisper(user, format!("<color=\"0a0\">{}</>", done));
+    }
+    whisper_legend(omegga, state, user, legend).await;
+    markers_placed(omegga, state, user, timeout);
+    if has_flag(args, "blink") {
+        let style = marker_style_arg(args).unwrap_or(config.marker_style);
+        blink_critical(omegga, state, user, save, config, chunks, style);
+    }
+    Ok(())
+}
+
+/// Makes the markers of the `chunks` over both the collider and component limit blink until other
+/// markers are placed or their markers are cleared.
+fn blink_critical(
+    omegga: &Arc<Omegga>,
+    state: &Arc<State>,
+    user: &str,
+    save: &AnalyzedSave,
+    config: &Config,
+    chunks: &[(ChunkPos, Option<&ChunkStats>)],
+    style: MarkerStyle,
+) {
+    let critical = chunks
+        .iter()
+        .filter_map(|(pos, stats)| Some((*pos, (*stats)?.clone())))
+        .filter(|(_, stats)| stats.colliders > COLLIDER_LIMIT && stats.components > COMPONENT_LIMIT)
+        .collect::<Vec<_>>();
+    if critical.is_empty() {
+        omegga.whisper(user, "<color=\"aa0\">None of the marked chunks are over both limits, so none blink.</>");
+        return;
+    }
+
+    let omegga = omegga.clone();
+    let state = state.clone();
+    let (grid, config) = (save.grid, config.clone());
+    let generation = state.marker_generation.load(Ordering::SeqCst);
+    tokio::spawn(async move {
+        let owner = blink_owner(&config).id.to_string();
+        let mut lit = false;
+        while state.marker_generation.load(Ordering::SeqCst) == generation {
+            // chunks cleared or undone since stop blinking
+            let marked = {
+                let markers = state.markers.read().await;
+                critical
+                    .iter()
+                    .filter(|(pos, _)| *markers.grid() == grid && markers.is_marked(*pos))
+                    .map(|(pos, stats)| (*pos, stats))
+                    .collect::<Vec<_>>()
+            };
+            if marked.is_empty() {
+                break;
+            }
+            if lit {
+                omegga.clear_bricks(owner.clone(), true);
+            } else if let Err(e) = omegga.load_save_data(mark_blink(&marked, &grid, &config, style), true, (0, 0, 0)).await {
+                omegga.error(format!("Failed to blink the markers of critical chunks: {}", e));
+                break;
+            }
+            lit = !lit;
+            sleep(BLINK_INTERVAL).await;
+        }
+        omegga.clear_bricks(owner, true);
+    });
+}
+
+/// Tells `user` what the colors of the markers they placed mean, unless they turned legends off
+/// with `/chunks legend off`.
+async fn whisper_legend(omegga: &Omegga, state: &State, user: &str, legend: Vec<String>) {
+    if state.hidden_legends.read().await.contains(user) {
+        return;
+    }
+    for line in legend {
+        omegga.whisper(user, line);
+    }
+}
+
+/// Loads the markers in `marker_data` a batch of chunks at a time, waiting a little between
+/// batches so big markings don't hitch the server, and tells `user` how far along it is.
+async fn load_markers(
+    omegga: &Omegga,
+    user: Option<&str>,
+    marker_data: SaveData,
+    grid: &Grid,
+    config: &Config,
+) -> Result<()> {
+    let batches = marker_batches(marker_data, grid, config.marker_batch_chunks, config);
+    let count = batches.len();
+    if count > 1 {
+        notify(omegga, user, format!("Placing the markers in <b>{}</> batches...", count));
+    }
+    let mut reported = Instant::now();
+    for (i, batch) in batches.into_iter().enumerate() {
+        if i > 0 {
+            sleep(Duration::from_millis(config.marker_batch_delay_ms)).await;
+        }
+        omegga.load_save_data(batch, true, (0, 0, 0)).await?;
+        if i + 1 < count && reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            notify(omegga, user, format!("Placed markers in batch <b>{}</> of <b>{}</>...", i + 1, count));
+        }
+    }
+    Ok(())
+}
+
+/// Adds the empty chunks next to the heavy chunks among `chunks` to them, those at over
+/// `heavy_chunk_percent` of a limit, so builders can see where there is room to build instead.
+fn with_expansion_space<'a>(
+    save: &'a AnalyzedSave,
+    config: &Config,
+    chunks: &[(ChunkPos, Option<&'a ChunkStats>)],
+) -> Vec<(ChunkPos, Option<&'a ChunkStats>)> {
+    let mut marked = chunks.to_vec();
+    let mut seen = chunks.iter().map(|(pos, _)| *pos).collect::<HashSet<_>>();
+    let height = if save.grid.columns { 0 } else { 1 };
+    for (pos, stats) in chunks.iter() {
+        let heavy = stats.is_some_and(|stats| severity(stats, config).0 * 100.0 > config.heavy_chunk_percent);
+        if !heavy {
+            continue;
+        }
+        for_each_chunk(
+            (pos.0 - 1, pos.1 - 1, pos.2 - height),
+            (pos.0 + 1, pos.1 + 1, pos.2 + height),
+            |next| {
+                if !save.chunks.contains_key(&next) && seen.insert(next) {
+                    marked.push((next, None));
+                }
+            },
+        );
+    }
+    marked
+}
+
+/// Finds the server's builds folder from where its saves are, if it has any.
+async fn builds_dir(omegga: &Omegga) -> Result<Option<PathBuf>> {
+    let mut saves = vec![SAVE_NAME.to_owned()];
+    saves.extend(omegga.get_saves().await?);
+    for save in saves {
+        let path = match omegga.get_save_path(save).await? {
+            Some(path) => PathBuf::from(path),
+            None => continue,
+        };
+        if let Some(dir) = path.parent() {
+            return Ok(Some(dir.to_path_buf()));
+        }
+    }
+    Ok(None)
+}
+
+/// Places the markers in `marker_data`, replacing the markers already in the chunks they are in so
+/// no chunk is marked twice, or the floor or minimap placed the same way, or every marker if the
+/// marking has the option `replace`.
+async fn place_marker_data(
+    omegga: &Omegga,
+    state: &State,
+    user: &str,
+    marker_data: SaveData,
+    grid: &Grid,
+    config: &Config,
+    marking: &Marking,
+) -> Result<()> {
+    let mut markers = state.markers.write().await;
+    let replace_all = has_flag(&marking.args, "replace");
+    if markers.place(&marker_data.bricks, grid, marking, replace_all) {
+        // bricks can't be cleared one chunk at a time, so clear them all and load the rest back
+        omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+        load_markers(omegga, Some(user), markers.save(config), grid, config).await
+    } else {
+        load_markers(omegga, Some(user), marker_data, grid, config).await
+    }
+}
+
+/// Draws the markers of `chunks` with the marking options in `args`, which must be valid. Also
+/// returns the legend of the owner colors when `owners` colors them by owner.
+fn draw_markers(
+    save: &AnalyzedSave,
+    config: &Config,
+    args: &[String],
+    chunks: &[(ChunkPos, Option<&ChunkStats>)],
+    palette: &Palette,
+) -> (SaveData, Legend) {
+    let style = marker_style_arg(args).unwrap_or(config.marker_style);
+    if has_flag(args, "owners") {
+        let chunks = chunks
+            .iter()
+            .filter_map(|(pos, stats)| Some((*pos, (*stats)?)))
+            .collect::<Vec<_>>();
+        let (marker_data, legend) = mark_owners(&chunks, &save.grid, config, style, palette);
+        return (marker_data, Legend::Owners(legend));
+    }
+
+    let by = mark_by_arg(args).ok().flatten();
+    let heatmap = (has_flag(args, "heatmap") || config.marker_coloring == MarkerColoring::Heatmap)
+        .then(|| Heatmap::new(save.chunks.values(), config));
+    let legend = marker_legend(config, palette, by, heatmap.as_ref());
+    (mark_chunks(chunks, &save.grid, config, style, palette, by, heatmap.as_ref()), Legend::Colors(legend))
+}
+
+/// What the colors of markers drawn by `draw_markers` mean.
+enum Legend {
+    /// Lines of chat explaining the colors of chunk markers.
+    Colors(Vec<String>),
+    /// The owners marked in each color, when marking by owner.
+    Owners(OwnerLegend),
+}
+
+/// Tells `user` which owner each color of owner markers belongs to, out of the `marked` chunks.
+fn whisper_owner_legend(omegga: &Omegga, user: &str, save: &AnalyzedSave, legend: &OwnerLegend, marked: usize) {
+    let owners = legend
+        .iter()
+        .map(|(owner, count, color)| {
+            format!(
+                "<color=\"{:02x}{:02x}{:02x}\"><b>{}</></> ({})",
+                color.r,
+                color.g,
+                color.b,
+                save.owner_name(*owner),
+                count,
+            )
+        })
+        .collect::<Vec<_>>();
+    omegga.whisper(user, format!("Chunks marked by the owner with the most bricks: {}", owners.join(", ")));
+    let colored = legend.iter().map(|(_, count, _)| count).sum::<usize>();
+    if colored < marked {
+        omegga.whisper(user, "Chunks of every other owner are <color=\"808080\"><b>gray</></>.");
+    }
+}
+
+/// Draws the markers in the world again with the options they were placed with, so their colors
+/// show the analysis `save` instead of the one they were placed with. Markers placed on another
+/// grid are left alone, since their chunks are gone.
+async fn refresh_markers(
+    omegga: &Omegga,
+    state: &State,
+    user: Option<&str>,
+    save: &AnalyzedSave,
+    config: &Config,
+) -> Result<bool> {
+    let mut markers = state.markers.write().await;
+    if markers.is_empty() || *markers.grid() != save.grid {
+        return Ok(false);
+    }
+    let markings = markers.markings();
+    omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+    markers.clear();
+
+    for (marking, marked) in markings {
+        let palette = user_palette(state, &marking.user, config).await;
+        let every_chunk = save.chunks.iter().map(|(pos, stats)| (*pos, stats)).collect::<Vec<_>>();
+        let marker_data = match marking.kind {
+            MarkingKind::Floor(z) => mark_floor(&every_chunk, &save.grid, config, &palette, z),
+            MarkingKind::Minimap(scale, origin) => mark_minimap(&every_chunk, config, &palette, scale, origin),
+            MarkingKind::Chunks => {
+                let chunks = marked.iter().map(|pos| (*pos, save.chunks.get(pos))).collect::<Vec<_>>();
+                draw_markers(save, config, &marking.args, &chunks, &palette).0
+            }
+        };
+        markers.add(&marker_data.bricks, &save.grid, &marking);
+        load_markers(omegga, user, marker_data, &save.grid, config).await?;
+    }
+    Ok(true)
+}
+
+async fn run_command(
+    omegga: Arc<Omegga>,
+    state: Arc<State>,
+    user: String,
+    args: Vec<String>,
+) -> Result<()> {
+    let config_read = state.config.read().await;
+    let config = match &*config_read {
+        Some(cfg) => cfg,
+        None => return Ok(()),
+    };
+
+    if !config
+        .authorized
+        .iter()
+        .any(|u| u.name.to_lowercase() == user.to_lowercase())
+    {
+        omegga.whisper(
+            user,
+            "<color=\"a00\">You are not authorized to use this command!</>",
+        );
+        return Ok(());
+    }
+
+    // only subcommands in the table `/chunks help` lists are run
+    let subcommand = match args.first() {
+        Some(name) => match find_subcommand(name) {
+            Some(subcommand) => subcommand,
+            None => {
+                omegga.whisper(user, format!(
+                    "<color=\"a00\">Unknown subcommand <b>{}</>! {}</>",
+                    name,
+                    COMMAND_USAGE,
+                ));
+                return Ok(());
+            }
+        },
+        None => {
+            omegga.whisper(user, format!("<color=\"a00\">{}</>", COMMAND_USAGE));
+            return Ok(());
+        }
+    };
+    match subcommand.name {
+        "help" => {
+            // explain the subcommands, or every form of one
+            match args.get(1) {
+                Some(name) => match find_subcommand(name) {
+                    Some(subcommand) => {
+                        let lines = subcommand
+                            .usages
+                            .iter()
+                            .map(|(usage, description)| format!("<code>/chunks {}</>: {}", usage, description))
+                            .collect();
+                        whisper_pages(&omegga, &state, &user, format!("<b>/chunks {}</>:", subcommand.name), lines).await;
+                    }
+                    None => omegga.whisper(user, format!(
+                        "<color=\"a00\">Unknown subcommand <b>{}</>! List the subcommands with <code>/chunks help</>.</>",
+                        name,
+                    )),
+                },
+                None => {
+                    let lines = SUBCOMMANDS
+                        .iter()
+                        .map(|subcommand| format!("<code>/chunks {}</>: {}", subcommand.name, subcommand.summary))
+                        .collect();
+                    let title = "Subcommands, explained further with <code>/chunks help <subcommand></>:".to_owned();
+                    whisper_pages(&omegga, &state, &user, title, lines).await;
+                }
+            }
+        }
+        "page" => {
+            // whisper another page of the latest long result
+            let mut pages = state.pages.write().await;
+            let pages = match pages.get_mut(&user) {
+                Some(pages) => pages,
+                None => {
+                    omegga.whisper(user, "<color=\"a00\">There is no long result to page through!</>");
+                    return Ok(());
+                }
+            };
+            let args = Args::new(&args, "Usage: <code>/chunks page [n]</>");
+            let page = match args.get(1) {
+                Some(_) => match args.parse::<usize>(1) {
+                    Ok(page) if (1..=pages.count()).contains(&page) => page,
+                    _ => {
+                        omegga.whisper(user, format!(
+                            "<color=\"a00\">{}, with n from 1 to {}</>",
+                            args.usage(),
+                            pages.count(),
+                        ));
+                        return Ok(());
+                    }
+                },
+                None if pages.page < pages.count() => pages.page + 1,
+                None => {
+                    omegga.whisper(user, "<color=\"a00\">That was the last page!</>");
+                    return Ok(());
+                }
+            };
+            pages.whisper(&omegga, &user, page);
+        }
+        "analyze" => {
+            let grid = active_grid(&state, config).await;
+            let request = match parse_analysis_request(&args[1..], &grid) {
+                Ok(request) => request,
+                Err(e) => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", e));
+                    return Ok(());
+                }
+            };
+
+            analyze(&omegga, &state, config, Some(&user), request).await?;
+        }
+        "status" => {
+            // show what the plugin is up to
+            match &*state.analyzed_save.read().await {
+                Some(save) => omegga.whisper(user.clone(), format!(
+                    "The latest analysis has <b>{}</> chunks with bricks and is <b>{}</> old.",
+                    save.chunks.len(),
+                    format_age(unix_now().saturating_sub(save.analyzed_at)),
+                )),
+                None => omegga.whisper(user.clone(), NOT_ANALYZED),
+            }
+            let auto = match *state.auto_interval.read().await {
+                Some(interval) => format!("<color=\"0a0\">on</>, every {} minutes", interval.as_secs() / 60),
+                None => "<color=\"a00\">off</>".to_owned(),
+            };
+            omegga.whisper(user.clone(), format!("Automatic analysis is <b>{}</>.", auto));
+            omegga.whisper(user, format!(
+                "<b>{}</> chunks are marked.",
+                state.markers.read().await.chunk_count(),
+            ));
+        }
+        "auto" => {
+            // control automatic analysis
+            let mut auto_interval = state.auto_interval.write().await;
+            let args = Args::new(&args, "Usage: <code>/chunks auto [on|off|interval minutes]</>");
+            match (args.get(1), args.try_parse::<u64>(2)) {
+                (Some("on"), _) => {
+                    let minutes = match config.auto_analysis_interval {
+                        0 => AUTO_DEFAULT_MINUTES,
+                        minutes => minutes,
+                    };
+                    *auto_interval = Some(Duration::from_secs(minutes * 60));
+                }
+                (Some("off"), _) => *auto_interval = None,
+                (Some("interval"), Some(minutes)) if minutes > 0 => {
+                    *auto_interval = Some(Duration::from_secs(minutes * 60));
+                }
+                (None, _) => (),
+                _ => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", args.usage()));
+                    return Ok(());
+                }
+            }
+            match *auto_interval {
+                Some(interval) => omegga.whisper(user, format!(
+                    "Automatic analysis is <b><color=\"0a0\">on</></>, every <b>{} minutes</>.",
+                    interval.as_secs() / 60,
+                )),
+                None => omegga.whisper(user, "Automatic analysis is <b><color=\"a00\">off</></>."),
+            }
+        }
+        "grid" => {
+            // switch between the configured grid and its presets
+            let mut grid_preset = state.grid_preset.write().await;
+            match args.get(1).map(|a| a.to_lowercase()) {
+                Some(name) if name == "default" => *grid_preset = None,
+                Some(name) => {
+                    if Grid::preset(config, &name).is_none() {
+                        omegga.whisper(user, format!(
+                            "<color=\"a00\">There is no grid preset named <b>{}</>! Presets: {}</>",
+                            name,
+                            format_names(config.grid_preset_map().keys()),
+                        ));
+                        return Ok(());
+                    }
+                    *grid_preset = Some(name);
+                }
+                None => omegga.whisper(user.clone(), format!(
+                    "Grid presets: {}",
+                    format_names(config.grid_preset_map().keys()),
+                )),
+            }
+            let grid = match &*grid_preset {
+                Some(name) => Grid::preset(config, name).unwrap_or_else(|| Grid::new(config)),
+                None => Grid::new(config),
+            };
+            omegga.whisper(user, format!(
+                "Using the <b>{}</> grid, with chunks of <b>{}x{}x{}</>. Reanalyze the save to apply a new grid.",
+                grid_preset.as_deref().unwrap_or("default"),
+                grid.size.0,
+                grid.size.1,
+                grid.size.2,
+            ));
+        }
+        "follow" => {
+            // outline the chunk the current player is in wherever they go
+            let mut following = state.following.write().await;
+            let on = match args.get(1).map(|a| a.to_lowercase()).as_deref() {
+                Some("on") => true,
+                Some("off") => false,
+                None => !following.contains_key(&user),
+                Some(_) => {
+                    omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks follow [on|off]</></>");
+                    return Ok(());
+                }
+            };
+            if !on {
+                following.remove(&user);
+                omegga.whisper(user, "<color=\"0a0\">Your chunk is no longer outlined.</>");
+            } else if !following.contains_key(&user) {
+                let started = Instant::now();
+                following.insert(user.clone(), started);
+                tokio::spawn(follow_player(omegga.clone(), state.clone(), user.clone(), started));
+                omegga.whisper(user, "<color=\"0a0\">The chunk you're in is now outlined wherever you go. Stop with <code>/chunks follow off</>.</>");
+            } else {
+                omegga.whisper(user, "Your chunk is already outlined.");
+            }
+        }
+        "palette" => {
+            // pick the colors markers are drawn in for the current player
+            let names = MarkerPalette::ALL.iter().map(|p| p.name()).collect::<Vec<_>>().join(", ");
+            let mut palettes = state.palettes.write().await;
+            match args.get(1).map(|a| a.to_lowercase()) {
+                Some(name) if name == "default" => {
+                    palettes.remove(&user);
+                }
+                Some(name) => match MarkerPalette::from_name(&name) {
+                    Some(palette) => {
+                        palettes.insert(user.clone(), palette);
+                    }
+                    None => {
+                        omegga.whisper(user, format!(
+                            "<color=\"a00\">There is no palette named <b>{}</>! Palettes: {}</>",
+                            name,
+                            names,
+                        ));
+                        return Ok(());
+                    }
+                },
+                None => omegga.whisper(user.clone(), format!("Palettes: {}", names)),
+            }
+            if args.len() > 1 {
+                match serde_json::to_value(&*palettes) {
+                    Ok(value) => omegga.store_set(PALETTES_STORE_KEY, value),
+                    Err(e) => omegga.error(format!("Failed to store the marker palettes: {}", e)),
+                }
+            }
+            let palette = palettes.get(&user).copied().unwrap_or(config.marker_palette);
+            omegga.whisper(user, format!(
+                "Your markers are drawn with the <b>{}</> palette. Place them again to apply a new palette.",
+                palette.name(),
+            ));
+        }
+        "legend" => {
+            // show or hide what marker colors mean after marking, for the current player
+            let mut hidden = state.hidden_legends.write().await;
+            match args.get(1).map(|a| a.to_lowercase()).as_deref() {
+                Some("on") => {
+                    hidden.remove(&user);
+                }
+                Some("off") => {
+                    hidden.insert(user.clone());
+                }
+                Some(_) => {
+                    omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks legend [on|off]</></>");
+                    return Ok(());
+                }
+                None => (),
+            }
+            if args.len() > 1 {
+                match serde_json::to_value(&*hidden) {
+                    Ok(value) => omegga.store_set(LEGENDS_STORE_KEY, value),
+                    Err(e) => omegga.error(format!("Failed to store who hid marker legends: {}", e)),
+                }
+            }
+            let shown = if hidden.contains(&user) { "not explained" } else { "explained" };
+            omegga.whisper(user, format!("Marker colors are <b>{}</> after you mark chunks.", shown));
+        }
+        "reloadcolliders" => {
+            // re-read colliders.json, which applies to the next analysis
+            *state.last_fingerprint.write().await = None;
+            match task::spawn_blocking(reload_colliders).await? {
+                Ok(count) => omegga.whisper(user, format!(
+                    "<color=\"0a0\">Reloaded the collider database with <b>{} assets</>. Reanalyze the save to apply it.</>",
+                    count,
+                )),
+                Err(e) => omegga.whisper(user, format!("<color=\"a00\">Failed to reload the collider database: {}</>", e)),
+            }
+        }
+        "in" => {
+            // find the chunk the current player is in
+            let grid = active_grid(&state, config).await;
+            let chunk_pos = player_chunk(&omegga, &user, &grid).await?;
+            if grid.columns {
+                omegga.whisper(user, format!("You are in column {:?}.", (chunk_pos.0, chunk_pos.1)));
+            } else {
+                omegga.whisper(user, format!("You are in chunk {:?}.", chunk_pos));
+            }
+        }
+        "count" => {
+            // list the bricks/colliders in this chunk
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunk_pos = player_chunk(&omegga, &user, &save.grid).await?;
+                    if let Some(stats) = save.chunks.get(&chunk_pos) {
+                        omegga.whisper(user.clone(), format!(
+                            "There are <b>{} bricks</>, <b><color=\"{}\">{} colliders</></>, <b>{} components</>, and <b>{} material variants</> in the chunk {:?}.",
+                            stats.bricks,
+                            limit_color(stats.colliders, COLLIDER_LIMIT),
+                            stats.colliders,
+                            stats.components,
+                            stats.materials.len(),
+                            chunk_pos,
+                        ));
+                        omegga.whisper(user.clone(), format!(
+                            "<b>{}</> physical bricks, <b>{}</> ghost bricks (no player or weapon collision), and <b>{}</> invisible bricks.",
+                            stats.bricks - stats.ghost_bricks,
+                            stats.ghost_bricks,
+                            stats.invisible_bricks,
+                        ));
+                        if stats.duplicate_bricks > 0 {
+                            omegga.whisper(user.clone(), format!(
+                                "<color=\"aa0\"><b>{}</> bricks in this chunk are exact duplicates of other bricks, likely from pasting twice.</>",
+                                stats.duplicate_bricks,
+                            ));
+                        }
+                        omegga.whisper(user.clone(), format!(
+                            "Approximate render weight: <b>~{} triangles</>. Approximate save size: <b>~{:.1} KiB</>. Density: <b>{:.2}</> bricks per cubic stud. Load score: <b>{:.0}</>/100.",
+                            stats.triangles,
+                            stats.save_bytes() as f64 / 1024.0,
+                            stats.density(),
+                            load_score(stats, config),
+                        ));
+                        omegga.whisper(user.clone(), [
+                            format_limited("wire/logic components", stats.wires, config.wire_limit),
+                            format_limited("lights", stats.lights, config.light_limit),
+                            format_limited("audio emitters", stats.audio_emitters, config.audio_limit),
+                            format_limited("item spawners", stats.spawners, config.spawner_limit),
+                            format_limited("interact components", stats.interacts, config.interact_limit),
+                            format_limited("particle emitters", stats.emitters, config.emitter_limit),
+                            format_limited("glowing bricks", stats.glow_bricks, config.glow_limit),
+                            format_limited("bytes of component data", Metric::Payload.value(stats), config.payload_limit),
+                        ].join(", "));
+                        if stats.components > COMPONENT_LIMIT {
+                            omegga.whisper(user, format!(
+                                "<color=\"a00\">This chunk is over the component limit.</> Most common components: {}",
+                                format_component_types(&stats.top_component_types()),
+                            ));
+                        }
+                    } else {
+                        omegga.whisper(user, "<color=\"a00\">This chunk has no bricks or colliders!</>");
+                    }
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "blame" => {
+            // list the owners contributing the most to this chunk
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    let chunk_pos = player_chunk(&omegga, &user, &save.grid).await?;
+                    match save.chunks.get(&chunk_pos) {
+                        Some(stats) => {
+                            let mut owners = stats.owners.iter().collect::<Vec<_>>();
+                            owners.sort_by(|a, b| b.1.colliders.cmp(&a.1.colliders).then(b.1.bricks.cmp(&a.1.bricks)));
+
+                            omegga.whisper(user.clone(), format!("Top contributors to the chunk {:?}:", chunk_pos));
+                            for (owner, counts) in owners.into_iter().take(BLAME_COUNT) {
+                                omegga.whisper(user.clone(), format!(
+                                    "<b>{}</>: {} bricks, {} colliders, {} components",
+                                    save.owner_name(*owner),
+                                    counts.bricks,
+                                    counts.colliders,
+                                    counts.components,
+                                ));
+                            }
+                        }
+                        None => omegga.whisper(user, "<color=\"a00\">This chunk has no bricks or colliders!</>"),
+                    }
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "assets" => {
+            // list the assets contributing the most colliders to this chunk
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    let chunk_pos = player_chunk(&omegga, &user, &save.grid).await?;
+                    match save.chunks.get(&chunk_pos) {
+                        Some(stats) => {
+                            let mut assets = stats.assets.iter().collect::<Vec<_>>();
+                            assets.sort_by(|a, b| b.1.colliders.cmp(&a.1.colliders).then(b.1.bricks.cmp(&a.1.bricks)));
+
+                            omegga.whisper(user.clone(), format!("Top assets in the chunk {:?}:", chunk_pos));
+                            for (asset, counts) in assets.into_iter().take(ASSETS_COUNT) {
+                                omegga.whisper(user.clone(), format!(
+                                    "<b>{}</>: {} bricks, {} colliders ({:.1}%)",
+                                    save.asset_name(*asset),
+                                    counts.bricks,
+                                    counts.colliders,
+                                    counts.colliders as f64 / stats.colliders.max(1) as f64 * 100.0,
+                                ));
+                            }
+                        }
+                        None => omegga.whisper(user, "<color=\"a00\">This chunk has no bricks or colliders!</>"),
+                    }
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "components" => {
+            // list the component types in this chunk
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    let chunk_pos = player_chunk(&omegga, &user, &save.grid).await?;
+                    match save.chunks.get(&chunk_pos) {
+                        Some(stats) if !stats.component_types.is_empty() => {
+                            omegga.whisper(user, format!(
+                                "Components in the chunk {:?} ({}/{}): {}",
+                                chunk_pos,
+                                stats.components,
+                                COMPONENT_LIMIT,
+                                format_component_types(&stats.top_component_types()),
+                            ));
+                        }
+                        _ => omegga.whisper(user, "<color=\"a00\">This chunk has no components!</>"),
+                    }
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "unknowns" => {
+            // list the assets missing from the collider database
+            match &*state.analyzed_save.read().await {
+                Some(save) if save.unknown_assets.is_empty() => {
+                    omegga.whisper(user, "<color=\"0a0\">Every asset in the save is in the collider database.</>");
+                }
+                Some(save) => {
+                    let lines = save
+                        .unknown_assets
+                        .iter()
+                        .map(|(asset, bricks)| format!("<b>{}</>: {} bricks", asset, bricks))
+                        .collect();
+                    let title = "Assets missing from the collider database:".to_owned();
+                    whisper_pages(&omegga, &state, &user, title, lines).await;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "strays" => {
+            // list the chunks holding bricks outside the world bound
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    let mut chunks = save.chunks.iter().filter(|(_, s)| s.stray_bricks > 0).collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, "<color=\"0a0\">There are no bricks outside the world bound.</>");
+                        return Ok(());
+                    }
+                    chunks.sort_by(|a, b| b.1.stray_bricks.cmp(&a.1.stray_bricks).then(a.0.cmp(b.0)));
+
+                    let title = format!(
+                        "<color=\"aa0\"><b>{}</> chunks have bricks more than {} units from the origin:</>",
+                        chunks.len(),
+                        config.world_bound,
+                    );
+                    let lines = chunks
+                        .into_iter()
+                        .map(|(pos, stats)| {
+                            format!(
+                                "{:?}: <b>{}</> stray bricks, around {:?}",
+                                pos,
+                                stats.stray_bricks,
+                                save.grid.chunk_center(*pos),
+                            )
+                        })
+                        .collect();
+                    whisper_pages(&omegga, &state, &user, title, lines).await;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "diff" => {
+            // compare the chunks between two analyses, by default the last two
+            let mut metric = Metric::Colliders;
+            let mut names = vec![];
+            for arg in &args[1..] {
+                match Metric::from_name(arg) {
+                    Some(m) => metric = m,
+                    None => names.push(arg.as_str()),
+                }
+            }
+
+            // resolve the named analyses to compare, if any were given
+            let named = state.named_saves.read().await;
+            let mut resolved = vec![];
+            for name in names.iter().take(2) {
+                match named.get(*name) {
+                    Some(save) => resolved.push(Some(save.clone())),
+                    None => {
+                        omegga.whisper(user, format!(
+                            "<color=\"a00\">There is no analysis or metric named <b>{}</>! Named analyses: {}</>",
+                            name,
+                            format_names(named.keys()),
+                        ));
+                        return Ok(());
+                    }
+                }
+            }
+            drop(named);
+            let (before, after, before_label, after_label) = match (resolved.len(), names.as_slice()) {
+                (2, [a, b, ..]) => (resolved[0].clone(), resolved[1].clone(), *a, *b),
+                (1, [a, ..]) => (resolved[0].clone(), state.analyzed_save.read().await.clone(), *a, "the current analysis"),
+                _ => (
+                    state.previous_save.read().await.clone(),
+                    state.analyzed_save.read().await.clone(),
+                    "the previous analysis",
+                    "the current analysis",
+                ),
+            };
+
+            match (before, after) {
+                (Some(before), Some(after)) => {
+                    whisper_diff(&omegga, &user, &before, &after, (before_label, after_label), metric);
+                }
+                (None, Some(_)) => omegga.whisper(user, "<color=\"a00\">There is no earlier analysis to compare against! Analyze the save again after making changes.</>"),
+                _ => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "baseline" => {
+            // pin an analysis to track growth against, or compare against the pinned one
+            match args.get(1).map(String::as_str) {
+                Some("set") => {
+                    let save = match state.analyzed_save.read().await.clone() {
+                        Some(save) => save,
+                        None => {
+                            omegga.whisper(user, NOT_ANALYZED);
+                            return Ok(());
+                        }
+                    };
+                    // keep it across restarts, since baselines are usually compared against much later
+                    match serde_json::to_value(&*save) {
+                        Ok(value) => omegga.store_set(BASELINE_STORE_KEY, value),
+                        Err(e) => omegga.error(format!("Failed to store the baseline: {}", e)),
+                    }
+                    *state.baseline.write().await = Some(save);
+                    omegga.whisper(user, "<color=\"0a0\">The latest analysis is now the baseline. Compare against it with <code>/chunks baseline compare</>.</>");
+                }
+                Some("compare") => {
+                    let metric = match args.get(2) {
+                        Some(name) => match Metric::from_name(name) {
+                            Some(metric) => metric,
+                            None => {
+                                omegga.whisper(user, format!(
+                                    "<color=\"a00\">Unknown metric <b>{}</>! Try one of: {}</>",
+                                    name,
+                                    Metric::names(),
+                                ));
+                                return Ok(());
+                            }
+                        },
+                        None => Metric::Colliders,
+                    };
+                    let baseline = state.baseline.read().await.clone();
+                    let current = state.analyzed_save.read().await.clone();
+                    match (baseline, current) {
+                        (Some(baseline), Some(current)) => {
+                            let label = format!(
+                                "the baseline from {} ago",
+                                format_age(unix_now().saturating_sub(baseline.analyzed_at)),
+                            );
+                            whisper_diff(&omegga, &user, &baseline, &current, (&label, "the current analysis"), metric);
+                        }
+                        (None, _) => omegga.whisper(user, "<color=\"a00\">There is no baseline! Pin the latest analysis with <code>/chunks baseline set</>.</>"),
+                        (_, None) => omegga.whisper(user, NOT_ANALYZED),
+                    }
+                }
+                _ => omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks baseline set|compare [metric]</></>"),
+            }
+        }
+        "summary" => {
+            // report totals across the whole analysis
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    omegga.whisper(user.clone(), format!(
+                        "The save has <b>{} bricks</> in <b>{} chunks</>.",
+                        save.brick_count,
+                        save.chunks.len(),
+                    ));
+                    if save.duplicate_bricks > 0 {
+                        let duplicate_chunks = save.chunks.values().filter(|s| s.duplicate_bricks > 0).count();
+                        omegga.whisper(user.clone(), format!(
+                            "<color=\"aa0\"><b>{}</> bricks in <b>{}</> chunks are exact duplicates of other bricks.</>",
+                            save.duplicate_bricks,
+                            duplicate_chunks,
+                        ));
+                    }
+
+                    let over_limit = Metric::ALL
+                        .iter()
+                        .filter_map(|&m| m.limit(config).map(|limit| (m, limit)))
+                        .map(|(m, limit)| {
+                            let count = save.chunks.values().filter(|s| m.value(s) > limit).count();
+                            format!("<b>{}</> over the {} limit", count, m.label())
+                        })
+                        .collect::<Vec<_>>();
+                    omegga.whisper(user.clone(), format!("Chunks: {}.", over_limit.join(", ")));
+
+                    let worst = save
+                        .chunks
+                        .iter()
+                        .map(|(pos, stats)| (pos, severity(stats, config)))
+                        .max_by(|a, b| (a.1).0.total_cmp(&(b.1).0));
+                    if let Some((pos, (fraction, metric))) = worst {
+                        omegga.whisper(user.clone(), format!(
+                            "The worst chunk is {:?}, at <b><color=\"{}\">{:.0}%</></> of the {} limit.",
+                            pos,
+                            if fraction > 1.0 { "a00" } else { "0a0" },
+                            fraction * 100.0,
+                            metric.label(),
+                        ));
+                    }
+
+                    let health = health_score(save, config);
+                    let (rating, color) = health_rating(health);
+                    omegga.whisper(user, format!(
+                        "Server health: <b><color=\"{}\">{:.0}/100 ({})</></>.",
+                        color,
+                        health,
+                        rating,
+                    ));
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "stats" => {
+            // show how the load is distributed across occupied chunks
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    for metric in [Metric::Colliders, Metric::Components] {
+                        let mut values = save.chunks.values().map(|s| metric.value(s)).collect::<Vec<_>>();
+                        values.sort_unstable();
+                        omegga.whisper(user.clone(), format!(
+                            "<b>{}</>: min {}, median {}, p90 {}, p99 {}, max {}",
+                            metric.label(),
+                            percentile(&values, 0),
+                            percentile(&values, 50),
+                            percentile(&values, 90),
+                            percentile(&values, 99),
+                            percentile(&values, 100),
+                        ));
+                    }
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "top" => {
+            // list the heaviest chunks in the save
+            // without a metric, chunks are sorted by load score
+            let mut count = TOP_COUNT;
+            let mut metric = None;
+            for arg in &args[1..] {
+                if let Ok(n) = arg.parse::<usize>() {
+                    count = n.clamp(1, TOP_MAX);
+                } else if arg.eq_ignore_ascii_case("score") {
+                    metric = None;
+                } else if let Some(m) = Metric::from_name(arg) {
+                    metric = Some(m);
+                } else {
+                    omegga.whisper(user, format!(
+                        "<color=\"a00\">Unknown metric <b>{}</>! Try one of: score, {}</>",
+                        arg,
+                        Metric::names(),
+                    ));
+                    return Ok(());
+                }
+            }
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let mut chunks = save.chunks.iter().collect::<Vec<_>>();
+                    let metric = match metric {
+                        Some(metric) => metric,
+                        None => {
+                            let mut chunks = chunks
+                                .into_iter()
+                                .map(|(pos, stats)| (pos, stats, load_score(stats, config)))
+                                .collect::<Vec<_>>();
+                            chunks.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(b.0)));
+
+                            let lines = chunks
+                                .into_iter()
+                                .take(count)
+                                .map(|(pos, stats, score)| {
+                                    format!(
+                                        "{:?}: <b>{:.0}</>/100 ({} bricks, {} colliders, {} components)",
+                                        pos,
+                                        score,
+                                        stats.bricks,
+                                        stats.colliders,
+                                        stats.components,
+                                    )
+                                })
+                                .collect();
+                            let title = "Chunks with the highest load score:".to_owned();
+                            whisper_pages(&omegga, &state, &user, title, lines).await;
+                            return Ok(());
+                        }
+                    };
+                    chunks.sort_by(|a, b| metric.value(b.1).cmp(&metric.value(a.1)).then(a.0.cmp(b.0)));
+
+                    let lines = chunks
+                        .into_iter()
+                        .take(count)
+                        .map(|(pos, stats)| {
+                            let value = match metric.limit(config) {
+                                Some(limit) => format_limited(metric.label(), metric.value(stats), limit),
+                                None => format!("{} {}", metric.value(stats), metric.label()),
+                            };
+                            format!("{:?}: {}", pos, value)
+                        })
+                        .collect();
+                    let title = format!("Chunks with the most {}:", metric.label());
+                    whisper_pages(&omegga, &state, &user, title, lines).await;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "export" => {
+            // write an analysis to a file for processing outside of the game
+            const USAGE: &str = "<color=\"a00\">Usage: <code>/chunks export json|csv|png [name] [layer <z>]</></>";
+            let layer = match Args::new(&args, USAGE).after("layer").map(|z| z.map(str::parse::<i32>)) {
+                Ok(Some(Ok(z))) => Some(z),
+                Ok(None) => None,
+                _ => {
+                    omegga.whisper(user, USAGE);
+                    return Ok(());
+                }
+            };
+            let name = args.get(2).filter(|name| !name.eq_ignore_ascii_case("layer"));
+            let save = match name {
+                Some(name) => state.named_saves.read().await.get(name).cloned(),
+                None => state.analyzed_save.read().await.clone(),
+            };
+            let format = args.get(1).map(|f| f.to_lowercase());
+            match (format.as_deref(), save, name) {
+                (Some(format @ ("json" | "csv" | "png")), Some(save), _) => {
+                    let label = name.map_or("latest", String::as_str).to_owned();
+                    let format = format.to_owned();
+                    let config = config.clone();
+                    let palette = user_palette(&state, &user, &config).await;
+                    let export = task::spawn_blocking(move || match format.as_str() {
+                        "csv" => export_csv(&save, &config, &label),
+                        "png" => export_png(&save, &config, &palette, &label, layer),
+                        _ => export_json(&save, &label),
+                    });
+                    match export.await? {
+                        Ok(path) => omegga.whisper(user, format!(
+                            "<color=\"0a0\">The analysis was exported to <b>{}</>.</>",
+                            path.display(),
+                        )),
+                        Err(e) => omegga.whisper(user, format!("<color=\"a00\">Failed to export the analysis: {}</>", e)),
+                    }
+                }
+                (Some("json" | "csv" | "png"), None, Some(name)) => omegga.whisper(user, format!(
+                    "<color=\"a00\">There is no analysis named <b>{}</>! Named analyses: {}</>",
+                    name,
+                    format_names(state.named_saves.read().await.keys()),
+                )),
+                (Some("json" | "csv" | "png"), None, None) => omegga.whisper(user, NOT_ANALYZED),
+                _ => omegga.whisper(user, USAGE),
+            }
+        }
+        "import" => {
+            // load an exported analysis as the latest analysis
+            let file = match args.get(1) {
+                Some(file) => file.clone(),
+                None => {
+                    omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks import file</></>");
+                    return Ok(());
+                }
+            };
+            match task::spawn_blocking(move || import_json(&file)).await? {
+                Ok(imported) => {
+                    let chunks = imported.chunks.len();
+                    let previous = state.analyzed_save.write().await.replace(Arc::new(imported));
+                    if previous.is_some() {
+                        *state.previous_save.write().await = previous;
+                    }
+                    *state.last_fingerprint.write().await = None;
+                    omegga.whisper(user, format!(
+                        "<color=\"0a0\">Imported an analysis of <b>{}</> chunks. It is now the latest analysis.</>",
+                        chunks,
+                    ));
+                }
+                Err(e) => omegga.whisper(user, format!("<color=\"a00\">Failed to import the analysis: {}</>", e)),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("above")) => {
+            // mark every chunk over a percentage of a limit
+            const USAGE: &str = "<color=\"a00\">Usage: <code>/chunks mark above <percent> [metric]</></>";
+            let args = Args::new(&args, USAGE);
+            let percent = match args.try_percent(2) {
+                Some(percent) => percent,
+                None => {
+                    omegga.whisper(user, USAGE);
+                    return Ok(());
+                }
+            };
+            let metric = args.rest(3).iter().find_map(|arg| Metric::from_name(arg));
+            if let Some(metric) = metric.filter(|m| m.limit(config).is_none()) {
+                omegga.whisper(user, format!("<color=\"a00\">{} have no limit to take a percentage of!</>", metric.label()));
+                return Ok(());
+            }
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let fraction = |stats| match metric {
+                        Some(metric) => metric.fraction(stats, config).unwrap_or(0.0),
+                        None => severity(stats, config).0,
+                    };
+                    let chunks = save
+                        .chunks
+                        .iter()
+                        .filter(|(_, stats)| fraction(stats) * 100.0 > percent)
+                        .map(|(pos, stats)| (*pos, Some(stats)))
+                        .collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, format!(
+                            "<color=\"0a0\">No chunks are above {}% of the {} limit.</>",
+                            percent,
+                            metric.map_or("collider, component, or any other", |m| m.label()),
+                        ));
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, args.rest(3), &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("top")) => {
+            // mark only the chunks with the highest load score
+            let args = Args::new(&args, "Usage: <code>/chunks mark top <n></>");
+            let count = match args.parse::<usize>(2) {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", args.usage()));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let mut chunks = save
+                        .chunks
+                        .iter()
+                        .map(|(pos, stats)| (pos, stats, load_score(stats, config)))
+                        .collect::<Vec<_>>();
+                    chunks.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(b.0)));
+                    let chunks = chunks
+                        .into_iter()
+                        .take(count)
+                        .map(|(pos, stats, _)| (*pos, Some(stats)))
+                        .collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, "<color=\"a00\">There are no chunks with bricks to mark!</>");
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, args.rest(3), &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("owner")) => {
+            // mark the chunks where one owner has most of the bricks
+            let args = Args::new(&args, "Usage: <code>/chunks mark owner <name> [percent]</>");
+            let name = match args.required(2) {
+                Ok(name) => name,
+                Err(usage) => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+            let (percent, options) = match args.try_percent(3) {
+                Some(percent) => (percent, args.rest(4)),
+                None => (OWNER_SHARE_PERCENT, args.rest(3)),
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let owners = (0..=save.owners.len() as u32)
+                        .filter(|owner| save.owner_name(*owner).eq_ignore_ascii_case(name))
+                        .collect::<HashSet<_>>();
+                    if owners.is_empty() {
+                        omegga.whisper(user, format!("<color=\"a00\">No bricks in the save are owned by <b>{}</>!</>", name));
+                        return Ok(());
+                    }
+                    let chunks = save
+                        .chunks
+                        .iter()
+                        .filter(|(_, stats)| {
+                            let owned = stats
+                                .owners
+                                .iter()
+                                .filter(|(owner, _)| owners.contains(owner))
+                                .map(|(_, counts)| counts.bricks)
+                                .sum::<u32>();
+                            owned > 0 && owned as f64 / stats.bricks.max(1) as f64 * 100.0 > percent
+                        })
+                        .map(|(pos, stats)| (*pos, Some(stats)))
+                        .collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, format!(
+                            "<color=\"0a0\"><b>{}</> owns more than {}% of the bricks of no chunk.</>",
+                            name,
+                            percent,
+                        ));
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, options, &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("radius")) => {
+            // mark the chunks around the current player
+            let args = Args::new(&args, "Usage: <code>/chunks mark radius <n></>");
+            let radius = match args.parse::<i32>(2) {
+                Ok(radius) if radius >= 0 => radius.min(MARK_RADIUS_MAX),
+                _ => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", args.usage()));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let center = player_chunk(&omegga, &user, &save.grid).await?;
+                    let height = if save.grid.columns { 0 } else { radius };
+                    let mut chunks = vec![];
+                    for_each_chunk(
+                        (center.0 - radius, center.1 - radius, center.2 - height),
+                        (center.0 + radius, center.1 + radius, center.2 + height),
+                        |pos| {
+                            if let Some(stats) = save.chunks.get(&pos) {
+                                chunks.push((pos, Some(stats)));
+                            }
+                        },
+                    );
+                    if chunks.is_empty() {
+                        omegga.whisper(user, "<color=\"a00\">There are no bricks around you to mark!</>");
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, args.rest(3), &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("neighbors")) => {
+            // mark the current chunk and every chunk touching it, with bricks or not
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let center = player_chunk(&omegga, &user, &save.grid).await?;
+                    let height = if save.grid.columns { 0 } else { 1 };
+                    let mut chunks = vec![];
+                    for_each_chunk(
+                        (center.0 - 1, center.1 - 1, center.2 - height),
+                        (center.0 + 1, center.1 + 1, center.2 + height),
+                        |pos| chunks.push((pos, save.chunks.get(&pos))),
+                    );
+                    place_markers(&omegga, &state, &user, save, config, &args[2..], &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("layer")) => {
+            // mark every chunk at the same height as the current player
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let layer = player_chunk(&omegga, &user, &save.grid).await?.2;
+                    let chunks = save
+                        .chunks
+                        .iter()
+                        .filter(|(pos, _)| pos.2 == layer)
+                        .map(|(pos, stats)| (*pos, Some(stats)))
+                        .collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, "<color=\"a00\">There are no bricks at your height to mark!</>");
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, &args[2..], &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("region")) => {
+            // mark every chunk in a box of chunk coordinates
+            let args = Args::new(&args, "Usage: <code>/chunks mark region x1 y1 z1 x2 y2 z2</> in chunk coordinates");
+            let (min, max) = match args.region(2) {
+                Ok(region) => region,
+                Err(usage) => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let (min, max) = if save.grid.columns {
+                        ((min.0, min.1, 0), (max.0, max.1, 0))
+                    } else {
+                        (min, max)
+                    };
+                    if save.grid.chunk_range_bounds(min, max).is_none() {
+                        omegga.whisper(user, format!("<color=\"a00\">{}, inside the world</>", args.usage()));
+                        return Ok(());
+                    }
+                    // widened first, since the corners can be as far apart as an i32 allows
+                    let count = [(min.0, max.0), (min.1, max.1), (min.2, max.2)]
+                        .iter()
+                        .map(|&(lo, hi)| hi as i64 - lo as i64 + 1)
+                        .fold(1, i64::saturating_mul);
+                    if count > MARK_REGION_MAX {
+                        omegga.whisper(user, format!(
+                            "<color=\"a00\">That region has {} chunks, more than the {} that can be marked at once!</>",
+                            count,
+                            MARK_REGION_MAX,
+                        ));
+                        return Ok(());
+                    }
+                    let mut chunks = vec![];
+                    for_each_chunk(min, max, |pos| chunks.push((pos, save.chunks.get(&pos))));
+                    place_markers(&omegga, &state, &user, save, config, args.rest(8), &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("at")) => {
+            // mark the chunk at some chunk coordinates
+            let args = Args::new(&args, "Usage: <code>/chunks mark at x y z</> in chunk coordinates");
+            let chunk_pos = match args.chunk(2) {
+                Ok(chunk_pos) => chunk_pos,
+                Err(usage) => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunk_pos = if save.grid.columns { (chunk_pos.0, chunk_pos.1, 0) } else { chunk_pos };
+                    if !save.grid.contains_chunk(chunk_pos) {
+                        omegga.whisper(user, format!("<color=\"a00\">{}, inside the world</>", args.usage()));
+                        return Ok(());
+                    }
+                    let opt = save.chunks.get(&chunk_pos);
+                    place_markers(&omegga, &state, &user, save, config, args.rest(5), &[(chunk_pos, opt)]).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "mark" => {
+            // mark the chunk we're currently in
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunk_pos = player_chunk(&omegga, &user, &save.grid).await?;
+                    let opt = save.chunks.get(&chunk_pos);
+                    place_markers(&omegga, &state, &user, save, config, &args[1..], &[(chunk_pos, opt)]).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "markall" => {
+            // mark every analyzed chunk
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let mut chunks = vec![];
+                    for (pos, stats) in save.chunks.iter() {
+                        chunks.push((*pos, Some(stats)));
+                    }
+                    place_markers(&omegga, &state, &user, save, config, &args[1..], &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "markover" => {
+            // mark only the chunks over the collider or component limit
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunks = save
+                        .chunks
+                        .iter()
+                        .filter(|(_, stats)| stats.colliders > COLLIDER_LIMIT || stats.components > COMPONENT_LIMIT)
+                        .map(|(pos, stats)| (*pos, Some(stats)))
+                        .collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(user, "<color=\"0a0\">No chunks are over the collider or component limit.</>");
+                        return Ok(());
+                    }
+                    place_markers(&omegga, &state, &user, save, config, &args[1..], &chunks).await?;
+                }
+                None => omegga.whisper(user, NOT_ANALYZED),
+            }
+        }
+        "markfloor" => {
+            // lay a tile under every column of chunks with bricks
+            let z = match args.get(1).filter(|arg| !arg.eq_ignore_ascii_case("for")) {
+                Some(z) => match z.parse::<i32>() {
+                    Ok(z) => z,
+                    Err(_) => {
+                        omegga.whisper(&user, "<color=\"a00\">Usage: <code>/chunks markfloor [z] [for <seconds>]</></>");
+                        return Ok(());
+                    }
+                },
+                None => config.marker_floor_height,
+            };
+            let timeout = match marker_timeout_arg(&args[1..]) {
+                Ok(timeout) => timeout.or(config.marker_timeout()),
+                Err(usage) => {
+                    omegga.whisper(&user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunks = save.chunks.iter().map(|(pos, stats)| (*pos, stats)).collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(&user, "<color=\"a00\">There are no bricks to mark!</>");
+                        return Ok(());
+                    }
+                    let palette = user_palette(&state, &user, config).await;
+                    let marker_data = mark_floor(&chunks, &save.grid, config, &palette, z);
+                    let tiles = marker_data.bricks.len();
+                    let marking = Marking {
+                        user: user.clone(),
+                        args: args[1..].to_vec(),
+                        kind: MarkingKind::Floor(z),
+                    };
+                    place_marker_data(&omegga, &state, &user, marker_data, &save.grid, config, &marking).await?;
+                    omegga.whisper(&user, format!("<color=\"0a0\">{} columns have been tiled at a height of {}.</>", tiles, z));
+                    let legend = vec![palette.gradient_legend("load score", ["0", "50", "100"])];
+                    whisper_legend(&omegga, &state, &user, legend).await;
+                    markers_placed(&omegga, &state, &user, timeout);
+                }
+                None => omegga.whisper(&user, NOT_ANALYZED),
+            }
+        }
+        "minimap" => {
+            // build a small model of the build next to the current player
+            const USAGE: &str = "<color=\"a00\">Usage: <code>/chunks minimap <scale> [for <seconds>]</>, with a scale from 1 to 50</>";
+            let scale = match Args::new(&args, USAGE).parse::<i32>(1) {
+                Ok(scale) if (1..=MINIMAP_SCALE_MAX).contains(&scale) => scale,
+                _ => {
+                    omegga.whisper(&user, USAGE);
+                    return Ok(());
+                }
+            };
+            let timeout = match marker_timeout_arg(&args[2..]) {
+                Ok(timeout) => timeout.or(config.marker_timeout()),
+                Err(usage) => {
+                    omegga.whisper(&user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+
+            match &*state.analyzed_save.read().await {
+                Some(save) => {
+                    warn_if_stale(&omegga, &user, save, config);
+                    let chunks = save.chunks.iter().map(|(pos, stats)| (*pos, stats)).collect::<Vec<_>>();
+                    if chunks.is_empty() {
+                        omegga.whisper(&user, "<color=\"a00\">There are no bricks to build a minimap of!</>");
+                        return Ok(());
+                    }
+                    let origin = match omegga.get_player_position(&user).await? {
+                        Some((x, y, z)) => (x as i32 + MINIMAP_GAP, y as i32, z as i32),
+                        None => {
+                            omegga.whisper(&user, "<color=\"a00\">Could not find where you are!</>");
+                            return Ok(());
+                        }
+                    };
+                    let palette = user_palette(&state, &user, config).await;
+                    let marker_data = mark_minimap(&chunks, config, &palette, scale, origin);
+                    let marking = Marking {
+                        user: user.clone(),
+                        args: args[1..].to_vec(),
+                        kind: MarkingKind::Minimap(scale, origin),
+                    };
+                    place_marker_data(&omegga, &state, &user, marker_data, &save.grid, config, &marking).await?;
+                    omegga.whisper(&user, format!(
+                        "<color=\"0a0\">A minimap of {} chunks has been built next to you. Clear it with <code>/chunks clear</>.</>",
+                        chunks.len(),
+                    ));
+                    let legend = vec![palette.gradient_legend("load score", ["0", "50", "100"])];
+                    whisper_legend(&omegga, &state, &user, legend).await;
+                    markers_placed(&omegga, &state, &user, timeout);
+                }
+                None => omegga.whisper(&user, NOT_ANALYZED),
+            }
+        }
+        "undo" => {
+            // put the chunks changed by the latest marking back, by clearing every marker and
+            // loading the others back
+            let mut markers = state.markers.write().await;
+            if !markers.undo() {
+                omegga.whisper(user, "<color=\"a00\">There is no marking to undo!</>");
+                return Ok(());
+            }
+            omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+            if !markers.is_empty() {
+                load_markers(&omegga, Some(&user), markers.save(config), markers.grid(), config).await?;
+            }
+            omegga.whisper(user, "<color=\"0a0\">The latest marking has been undone.</>");
+        }
+        "clear" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case("region")) => {
+            // clear the markers in a box of chunk coordinates, and load the others back
+            let args = Args::new(&args, "Usage: <code>/chunks clear region x1 y1 z1 x2 y2 z2</> in chunk coordinates");
+            let (min, max) = match args.region(2) {
+                Ok(region) => region,
+                Err(usage) => {
+                    omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+                    return Ok(());
+                }
+            };
+
+            let mut markers = state.markers.write().await;
+            let (min, max) = if markers.grid().columns {
+                ((min.0, min.1, 0), (max.0, max.1, 0))
+            } else {
+                (min, max)
+            };
+            if markers.grid().chunk_range_bounds(min, max).is_none() {
+                omegga.whisper(user, format!("<color=\"a00\">{}, inside the world</>", args.usage()));
+                return Ok(());
+            }
+            let cleared = markers.remove_region(min, max);
+            if cleared == 0 {
+                omegga.whisper(user, "<color=\"a00\">No chunks in that region are marked!</>");
+                return Ok(());
+            }
+            omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+            if !markers.is_empty() {
+                load_markers(&omegga, Some(&user), markers.save(config), markers.grid(), config).await?;
+            }
+            omegga.whisper(user, format!(
+                "<color=\"0a0\">The markers of {} chunks between {:?} and {:?} have been cleared.</>",
+                cleared,
+                min,
+                max,
+            ));
+        }
+        "clear" if args.len() > 1 => {
+            // clear the markers of a single chunk, by clearing every marker and loading the others back
+            let grid = match &*state.analyzed_save.read().await {
+                Some(save) => save.grid,
+                None => active_grid(&state, config).await,
+            };
+            let chunk_pos = match args[1].to_lowercase().as_str() {
+                "here" => player_chunk(&omegga, &user, &grid).await?,
+                "at" => match Args::new(&args, "Usage: <code>/chunks clear at x y z</> in chunk coordinates").chunk(2) {
+                    Ok((x, y, _)) if grid.columns && grid.contains_chunk((x, y, 0)) => (x, y, 0),
+                    Ok(chunk_pos) if !grid.columns && grid.contains_chunk(chunk_pos) => chunk_pos,
+                    Ok(_) => {
+                        omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks clear at x y z</> in chunk coordinates, inside the world</>");
+                        return Ok(());
+                    }
+                    Err(usage) => {
+                        omegga.whisper(user, format!("<color=\"a00\">{}</>", usage));
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    omegga.whisper(user, "<color=\"a00\">Usage: <code>/chunks clear [here | at x y z | region x1 y1 z1 x2 y2 z2]</></>");
+                    return Ok(());
+                }
+            };
+
+            let mut markers = state.markers.write().await;
+            if !markers.remove(chunk_pos) {
+                omegga.whisper(user, format!("<color=\"a00\">The chunk {:?} isn't marked!</>", chunk_pos));
+                return Ok(());
+            }
+            omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+            if !markers.is_empty() {
+                load_markers(&omegga, Some(&user), markers.save(config), markers.grid(), config).await?;
+            }
+            omegga.whisper(user, format!("<color=\"0a0\">The markers of the chunk {:?} have been cleared.</>", chunk_pos));
+        }
+        "clear" => {
+            // clear chunk markers
+            omegga.clear_bricks(marker_owner(config).id.to_string(), true);
+            state.markers.write().await.clear();
+            omegga.whisper(user, "<color=\"0a0\">Chunk markers have been cleared.</>");
+        }
+        name => omegga.whisper(user, format!(
+            "<color=\"a00\">Unknown subcommand <b>{}</>! {}</>",
+            name,
+            COMMAND_USAGE,
+        )),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let sorted = (1..=10).collect::<Vec<u32>>();
+        assert_eq!(percentile(&sorted, 0), 1);
+        assert_eq!(percentile(&sorted, 50), 5);
+        assert_eq!(percentile(&sorted, 90), 9);
+        assert_eq!(percentile(&sorted, 91), 10);
+        assert_eq!(percentile(&sorted, 100), 10);
+        assert_eq!(percentile(&[7], 99), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
@@ -0,0 +1,1354 @@
+use std::collections::{HashMap, VecDeque};
+
+use brickadia::save::{
+    Brick, BrickColor, BrickOwner, Collision, Color, Component, Direction, Header2, Rotation, SaveData,
+    Size, UnrealType,
+};
+
+use crate::{
+    analysis::{ChunkStats, COLLIDER_LIMIT, COMPONENT_LIMIT},
+    chunk::{ChunkPos, Grid},
+    config::{Config, MarkerColoring, MarkerPalette, MarkerStyle},
+    metric::{load_score, Metric},
+};
+
+/// The id of the owner markers are placed under, unless another is configured.
+pub const MARKER_OWNER_UUID: &str = "00000000-0000-0000-0000-000000000001";
+
+/// The bits flipped in the fourth group of the marker owner's id to get the id of the owner of
+/// blinking markers.
+const BLINK_OWNER_BITS: u16 = 0x0002;
+
+/// The color blinking markers flash in.
+const BLINK_COLOR: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
+/// The longest brick drawn along a chunk edge, in world units. Longer edges are split into
+/// segments.
+const EDGE_SEGMENT_MAX: i32 = 1024;
+
+/// The asset and material indices of fill markers in marker saves, which are always a plain
+/// micro brick cube in `Config::marker_fill_material`.
+const FILL_ASSET: u32 = 1;
+const FILL_MATERIAL: u32 = 2;
+
+/// The asset index of wedge markers in marker saves, a micro corner wedge.
+const WEDGE_ASSET: u32 = 2;
+
+/// The half height of floor tiles, in world units: a plate.
+const FLOOR_TILE_HEIGHT: u32 = 2;
+
+/// How many markings can be undone.
+const UNDO_MAX: usize = 5;
+
+/// The component that shows a message to players clicking a brick.
+const INTERACT_COMPONENT: &str = "BCD_Interact";
+
+/// The colors of each chunk state with the standard palette: white for empty chunks, green
+/// for chunks below every limit, then red, blue, magenta, orange, yellow, and cyan for the limits
+/// they are over.
+#[rustfmt::skip]
+const MARKER_COLORS: [Color; 8] = [
+    Color { r: 255, g: 255, b: 255, a: 255 },
+    Color { r: 0, g: 255, b: 0, a: 255 },
+    Color { r: 255, g: 0, b: 0, a: 255 },
+    Color { r: 0, g: 0, b: 255, a: 255 },
+    Color { r: 255, g: 0, b: 255, a: 255 },
+    Color { r: 255, g: 128, b: 0, a: 255 },
+    Color { r: 255, g: 255, b: 0, a: 255 },
+    Color { r: 0, g: 255, b: 255, a: 255 },
+];
+
+/// The colors of each chunk state for red-green color blindness, from the Okabe-Ito palette.
+#[rustfmt::skip]
+const RED_GREEN_MARKER_COLORS: [Color; 8] = [
+    Color { r: 255, g: 255, b: 255, a: 255 },
+    Color { r: 0, g: 114, b: 178, a: 255 },
+    Color { r: 230, g: 159, b: 0, a: 255 },
+    Color { r: 86, g: 180, b: 233, a: 255 },
+    Color { r: 213, g: 94, b: 0, a: 255 },
+    Color { r: 204, g: 121, b: 167, a: 255 },
+    Color { r: 240, g: 228, b: 66, a: 255 },
+    Color { r: 0, g: 158, b: 115, a: 255 },
+];
+
+/// The colors of each chunk state for blue-yellow color blindness, which keeps to reds, teals,
+/// and grays.
+#[rustfmt::skip]
+const BLUE_YELLOW_MARKER_COLORS: [Color; 8] = [
+    Color { r: 255, g: 255, b: 255, a: 255 },
+    Color { r: 0, g: 160, b: 160, a: 255 },
+    Color { r: 220, g: 0, b: 0, a: 255 },
+    Color { r: 255, g: 140, b: 180, a: 255 },
+    Color { r: 120, g: 0, b: 0, a: 255 },
+    Color { r: 0, g: 80, b: 80, a: 255 },
+    Color { r: 128, g: 128, b: 128, a: 255 },
+    Color { r: 160, g: 0, b: 120, a: 255 },
+];
+
+/// The names of the chunk states in the marker color tables, by index, used to pick their colors in the
+/// config.
+const MARKER_STATES: [&str; 8] = [
+    "empty",
+    "ok",
+    "colliders",
+    "components",
+    "both",
+    "wires",
+    "lights",
+    "interacts",
+];
+
+/// Colors that are easy to tell apart, given to the owners with the most chunks when marking
+/// chunks by their dominant owner.
+#[rustfmt::skip]
+const OWNER_COLORS: [Color; 10] = [
+    Color { r: 230, g: 25, b: 75, a: 255 },
+    Color { r: 60, g: 180, b: 75, a: 255 },
+    Color { r: 255, g: 225, b: 25, a: 255 },
+    Color { r: 0, g: 130, b: 200, a: 255 },
+    Color { r: 245, g: 130, b: 48, a: 255 },
+    Color { r: 145, g: 30, b: 180, a: 255 },
+    Color { r: 70, g: 240, b: 240, a: 255 },
+    Color { r: 240, g: 50, b: 230, a: 255 },
+    Color { r: 210, g: 245, b: 60, a: 255 },
+    Color { r: 250, g: 190, b: 212, a: 255 },
+];
+
+/// Colors for owners that stay easy to tell apart with any kind of color blindness, from the
+/// Okabe-Ito palette.
+#[rustfmt::skip]
+const COLORBLIND_OWNER_COLORS: [Color; 8] = [
+    Color { r: 230, g: 159, b: 0, a: 255 },
+    Color { r: 86, g: 180, b: 233, a: 255 },
+    Color { r: 0, g: 158, b: 115, a: 255 },
+    Color { r: 240, g: 228, b: 66, a: 255 },
+    Color { r: 0, g: 114, b: 178, a: 255 },
+    Color { r: 213, g: 94, b: 0, a: 255 },
+    Color { r: 204, g: 121, b: 167, a: 255 },
+    Color { r: 0, g: 0, b: 0, a: 255 },
+];
+
+/// The color of every owner past the end of the owner colors of a palette.
+const OTHER_OWNER_COLOR: Color = Color {
+    r: 128,
+    g: 128,
+    b: 128,
+    a: 255,
+};
+
+/// The colors markers are drawn in: one of the built in palettes, with the colors of chunk states
+/// changed by `Config::marker_colors`.
+pub struct Palette {
+    states: [Color; 8],
+    /// The colors of the gradients markers are colored with, at 0, 50, and 100 percent.
+    scale: [Color; 3],
+    owners: &'static [Color],
+}
+
+impl Palette {
+    pub fn new(kind: MarkerPalette, config: &Config) -> Self {
+        let rgb = |r, g, b| Color { r, g, b, a: 255 };
+        let (states, scale, owners): (_, _, &'static [Color]) = match kind {
+            MarkerPalette::Standard => (
+                MARKER_COLORS,
+                [rgb(0, 255, 0), rgb(255, 255, 0), rgb(255, 0, 0)],
+                &OWNER_COLORS,
+            ),
+            // blue through yellow to orange, which stay apart without telling red from green
+            MarkerPalette::Deuteranopia => (
+                RED_GREEN_MARKER_COLORS,
+                [rgb(0, 114, 178), rgb(240, 228, 66), rgb(213, 94, 0)],
+                &COLORBLIND_OWNER_COLORS,
+            ),
+            // reds look dark without red cones, so the gradient ends on a bright orange
+            MarkerPalette::Protanopia => (
+                RED_GREEN_MARKER_COLORS,
+                [rgb(0, 114, 178), rgb(220, 220, 220), rgb(230, 159, 0)],
+                &COLORBLIND_OWNER_COLORS,
+            ),
+            MarkerPalette::Tritanopia => (
+                BLUE_YELLOW_MARKER_COLORS,
+                [rgb(0, 160, 160), rgb(240, 240, 240), rgb(220, 0, 0)],
+                &COLORBLIND_OWNER_COLORS,
+            ),
+        };
+
+        let mut palette = Palette {
+            states,
+            scale,
+            owners,
+        };
+        for (state, (r, g, b)) in config.marker_color_map() {
+            if let Some(i) = MARKER_STATES.iter().position(|s| *s == state) {
+                palette.states[i] = rgb(r, g, b);
+            }
+        }
+        palette
+    }
+
+    /// Returns the color of the chunk state at `state` in `MARKER_STATES`.
+    fn state(&self, state: usize) -> BrickColor {
+        BrickColor::Unique(self.states[state].clone())
+    }
+
+    /// Returns the color of a chunk at `percent` of the way up a gradient, like its load score.
+    fn scale(&self, percent: f64) -> BrickColor {
+        BrickColor::Unique(self.scale_color(percent))
+    }
+
+    /// Explains the palette's gradient when it colors markers by `what`, with the values at 0, 50,
+    /// and 100 percent of the way up it.
+    pub fn gradient_legend(&self, what: &str, values: [&str; 3]) -> String {
+        format!(
+            "Markers are colored by {}: {} through {} to {}.",
+            what,
+            color_tag(&self.scale[0], values[0]),
+            color_tag(&self.scale[1], values[1]),
+            color_tag(&self.scale[2], values[2]),
+        )
+    }
+
+    /// Returns the color `percent` of the way up the palette's gradient.
+    pub fn scale_color(&self, percent: f64) -> Color {
+        let t = (percent / 100.0).clamp(0.0, 1.0) * 2.0;
+        let (from, to, t) = if t < 1.0 {
+            (&self.scale[0], &self.scale[1], t)
+        } else {
+            (&self.scale[1], &self.scale[2], t - 1.0)
+        };
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        Color {
+            r: mix(from.r, to.r),
+            g: mix(from.g, to.g),
+            b: mix(from.b, to.b),
+            a: 255,
+        }
+    }
+}
+
+/// What markers are colored by when it is picked for a single marking, instead of
+/// `Config::marker_coloring`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkBy {
+    /// A gradient by the chunk's load score.
+    Score,
+    /// A gradient by how close the chunk is to the metric's limit, or to the marked chunk with
+    /// the most of it if the metric has no limit.
+    Metric(Metric),
+}
+
+impl MarkBy {
+    /// Finds what to color by called `name`: `score` or the name of a metric.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("score") {
+            return Some(MarkBy::Score);
+        }
+        Metric::from_name(name).map(MarkBy::Metric)
+    }
+}
+
+/// Splits chunks into steps by how their load score ranks among every chunk of an analysis, with
+/// about as many chunks in each step, so the busiest chunks stand out even when none are near a
+/// limit.
+pub struct Heatmap {
+    /// The lowest load score of each step past the first.
+    thresholds: Vec<f64>,
+}
+
+impl Heatmap {
+    pub fn new<'a>(chunks: impl Iterator<Item = &'a ChunkStats>, config: &Config) -> Self {
+        let mut scores = chunks
+            .map(|stats| load_score(stats, config))
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| a.total_cmp(b));
+        let steps = config.heatmap_steps.max(2) as usize;
+        let thresholds = match scores.len() {
+            0 => vec![],
+            n => (1..steps).map(|step| scores[step * n / steps]).collect(),
+        };
+        Heatmap { thresholds }
+    }
+
+    /// Returns how far up the steps a chunk with `stats` is, from 0 to 100.
+    fn percent(&self, stats: &ChunkStats, config: &Config) -> f64 {
+        if self.thresholds.is_empty() {
+            return 0.0;
+        }
+        let score = load_score(stats, config);
+        let step = self.thresholds.iter().filter(|t| score > **t).count();
+        step as f64 / self.thresholds.len() as f64 * 100.0
+    }
+}
+
+/// Returns what markers drawn by `mark_chunks` with `by` and `heatmap` are colored by, when they
+/// aren't colored by a metric.
+fn coloring(config: &Config, by: Option<MarkBy>, heatmap: Option<&Heatmap>) -> MarkerColoring {
+    match (by, heatmap) {
+        (Some(MarkBy::Score), _) => MarkerColoring::Load,
+        (None, Some(_)) => MarkerColoring::Heatmap,
+        _ => config.marker_coloring,
+    }
+}
+
+/// Explains what the colors of markers drawn by `mark_chunks` with the same options mean, and the
+/// thresholds behind them, as lines of chat.
+pub fn marker_legend(
+    config: &Config,
+    palette: &Palette,
+    by: Option<MarkBy>,
+    heatmap: Option<&Heatmap>,
+) -> Vec<String> {
+    let mut lines = vec![];
+    match (by, coloring(config, by, heatmap)) {
+        (Some(MarkBy::Metric(metric)), _) => match metric.limit(config) {
+            Some(limit) => lines.push(palette.gradient_legend(
+                &format!("{} out of the limit", metric.label()),
+                ["0", &(limit / 2).to_string(), &limit.to_string()],
+            )),
+            None => lines.push(palette.gradient_legend(
+                &format!("{}, next to the marked chunk with the most", metric.label()),
+                ["none", "half as many", "as many"],
+            )),
+        },
+        (_, MarkerColoring::Load) => lines.push(palette.gradient_legend("load score", ["0", "50", "100"])),
+        (_, MarkerColoring::Percent) => lines.push(palette.gradient_legend(
+            &format!(
+                "how close the chunk is to the collider ({}) or component ({}) limit",
+                COLLIDER_LIMIT, COMPONENT_LIMIT,
+            ),
+            ["0%", "50%", "100%"],
+        )),
+        (_, MarkerColoring::Density) => lines.push(palette.gradient_legend(
+            "bricks per cubic stud",
+            ["0", &(config.marker_density_full / 2.0).to_string(), &config.marker_density_full.to_string()],
+        )),
+        (_, MarkerColoring::Heatmap) => {
+            lines.push(palette.gradient_legend(
+                "how the load score ranks among every chunk",
+                ["lowest", "middle", "highest"],
+            ));
+            if let Some(heatmap) = heatmap.filter(|h| !h.thresholds.is_empty()) {
+                let thresholds = heatmap
+                    .thresholds
+                    .iter()
+                    .map(|t| format!("{:.0}", t))
+                    .collect::<Vec<_>>();
+                lines.push(format!("The steps start at load scores of {}.", thresholds.join(", ")));
+            }
+        }
+        (_, MarkerColoring::Limits) => {
+            let states = [
+                (0, "no bricks".to_owned()),
+                (1, "within every limit".to_owned()),
+                (2, format!("over {} colliders", COLLIDER_LIMIT)),
+                (3, format!("over {} components", COMPONENT_LIMIT)),
+                (4, "over both".to_owned()),
+                (5, format!("over {} wire components", config.wire_limit)),
+                (6, format!("over {} lights", config.light_limit)),
+                (7, format!("over {} interact components", config.interact_limit)),
+            ];
+            let states = states
+                .iter()
+                .map(|(i, meaning)| color_tag(&palette.states[*i], meaning))
+                .collect::<Vec<_>>();
+            lines.push(format!("Markers are colored by the limits chunks are over: {}.", states.join(", ")));
+            return lines;
+        }
+    }
+    lines.push(format!("Chunks without bricks are marked {}.", color_tag(&palette.states[0], "like this")));
+    lines
+}
+
+/// Returns `text` in chat colored `color`.
+fn color_tag(color: &Color, text: &str) -> String {
+    format!("<color=\"{:02x}{:02x}{:02x}\">{}</>", color.r, color.g, color.b, text)
+}
+
+pub fn mark_chunks(
+    chunks: &[(ChunkPos, Option<&ChunkStats>)],
+    grid: &Grid,
+    config: &Config,
+    style: MarkerStyle,
+    palette: &Palette,
+    by: Option<MarkBy>,
+    heatmap: Option<&Heatmap>,
+) -> SaveData {
+    let mut bricks = vec![];
+    // metrics without a limit are colored relative to the chunk with the most
+    let most = match by {
+        Some(MarkBy::Metric(metric)) if metric.limit(config).is_none() => chunks
+            .iter()
+            .filter_map(|(_, stats)| Some(metric.value((*stats)?)))
+            .max()
+            .unwrap_or(0),
+        _ => 0,
+    };
+
+    for (pos, opt) in chunks.iter() {
+        let col = match opt {
+            Some(stats) if stats.colliders > COLLIDER_LIMIT && stats.components > COMPONENT_LIMIT => 4,
+            Some(stats) if stats.components > COMPONENT_LIMIT => 3,
+            Some(stats) if stats.colliders > COLLIDER_LIMIT => 2,
+            Some(stats) if stats.wires > config.wire_limit => 5,
+            Some(stats) if stats.lights > config.light_limit => 6,
+            Some(stats) if stats.interacts > config.interact_limit => 7,
+            Some(_) => 1,
+            None => 0,
+        };
+        let (color, glow) = match (opt, by, coloring(config, by, heatmap)) {
+            (Some(stats), Some(MarkBy::Metric(metric)), _) => {
+                let fraction = metric
+                    .fraction(stats, config)
+                    .unwrap_or_else(|| metric.value(stats) as f64 / most.max(1) as f64);
+                (palette.scale(fraction * 100.0), true)
+            }
+            (Some(stats), _, MarkerColoring::Heatmap) => {
+                // without the rest of the analysis to rank against, fall back to the load score
+                let percent = heatmap
+                    .map_or_else(|| load_score(stats, config), |h| h.percent(stats, config));
+                (palette.scale(percent), true)
+            }
+            (Some(stats), _, MarkerColoring::Load) => (palette.scale(load_score(stats, config)), true),
+            (Some(stats), _, MarkerColoring::Percent) => {
+                let fraction = (stats.colliders as f64 / COLLIDER_LIMIT as f64)
+                    .max(stats.components as f64 / COMPONENT_LIMIT as f64);
+                (palette.scale(fraction * 100.0), true)
+            }
+            (Some(stats), _, MarkerColoring::Density) => {
+                let fraction = stats.density() / config.marker_density_full.max(f64::EPSILON);
+                (palette.scale(fraction * 100.0), true)
+            }
+            _ => (palette.state(col), col > 0),
+        };
+        let start = bricks.len();
+        let look = MarkerLook {
+            color,
+            glow,
+            size: marker_size(*opt, config),
+        };
+        push_markers(&mut bricks, *pos, grid, config, style, look);
+        if let (Some(stats), true) = (opt, config.marker_interact) {
+            add_interact(&mut bricks[start..], &chunk_message(*pos, stats, config));
+        }
+    }
+
+    marker_save(bricks, config)
+}
+
+/// Every owner given a color of their own by `mark_owners`, with the number of chunks they have
+/// the most bricks in and their color.
+pub type OwnerLegend = Vec<(u32, usize, Color)>;
+
+/// Marks `chunks` in the color of the owner with the most bricks in each of them. The owners
+/// with the most chunks get colors of their own, and the rest share gray.
+///
+/// Also returns the legend of the colors: every owner with a color of their own and the number of
+/// chunks they have the most bricks in, most first.
+pub fn mark_owners(
+    chunks: &[(ChunkPos, &ChunkStats)],
+    grid: &Grid,
+    config: &Config,
+    style: MarkerStyle,
+    palette: &Palette,
+) -> (SaveData, OwnerLegend) {
+    let dominant = chunks
+        .iter()
+        .filter_map(|(pos, stats)| Some((*pos, stats.dominant_owner()?, *stats)))
+        .collect::<Vec<_>>();
+
+    let mut owner_chunks = HashMap::<u32, usize>::new();
+    for (_, owner, _) in dominant.iter() {
+        *owner_chunks.entry(*owner).or_default() += 1;
+    }
+    let mut owners = owner_chunks.into_iter().collect::<Vec<_>>();
+    owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let legend = owners
+        .into_iter()
+        .zip(palette.owners.iter())
+        .map(|((owner, count), color)| (owner, count, color.clone()))
+        .collect::<Vec<_>>();
+
+    let mut bricks = vec![];
+    for (pos, owner, stats) in dominant.into_iter() {
+        let color = legend
+            .iter()
+            .find(|(o, _, _)| *o == owner)
+            .map_or(OTHER_OWNER_COLOR, |(_, _, color)| color.clone());
+        let start = bricks.len();
+        let look = MarkerLook {
+            color: BrickColor::Unique(color),
+            glow: true,
+            size: marker_size(Some(stats), config),
+        };
+        push_markers(&mut bricks, pos, grid, config, style, look);
+        if config.marker_interact {
+            add_interact(&mut bricks[start..], &chunk_message(pos, stats, config));
+        }
+    }
+
+    (marker_save(bricks, config), legend)
+}
+
+/// Covers the ground under `chunks` with a flat tile at the height `z` for every column of
+/// chunks, colored by the load score of the busiest chunk in the column, for a map of the whole
+/// build that players can walk on.
+pub fn mark_floor(
+    chunks: &[(ChunkPos, &ChunkStats)],
+    grid: &Grid,
+    config: &Config,
+    palette: &Palette,
+    z: i32,
+) -> SaveData {
+    let mut columns = HashMap::<(i32, i32), (ChunkPos, &ChunkStats, f64)>::new();
+    for (pos, stats) in chunks.iter() {
+        let score = load_score(stats, config);
+        let column = columns.entry((pos.0, pos.1)).or_insert((*pos, *stats, score));
+        if score > column.2 {
+            *column = (*pos, *stats, score);
+        }
+    }
+
+    let (x, y, _) = grid.size;
+    let size = ((x / 2).max(1) as u32, (y / 2).max(1) as u32, FLOOR_TILE_HEIGHT);
+    let mut bricks = vec![];
+    for (pos, stats, score) in columns.into_values() {
+        let (cx, cy, _) = grid.chunk_center(pos);
+        let position = (cx, cy, z + FLOOR_TILE_HEIGHT as i32);
+        let mut tile = Brick {
+            asset_name_index: FILL_ASSET,
+            ..marker_brick(position, size, &palette.scale(score), false)
+        };
+        if config.marker_interact {
+            add_interact(std::slice::from_mut(&mut tile), &chunk_message(pos, stats, config));
+        }
+        bricks.push(tile);
+    }
+
+    marker_save(bricks, config)
+}
+
+/// Builds a miniature of the chunks with bricks out of a cube for each chunk, `scale` units from
+/// its center to its sides and colored by its load score, with its lowest corner at `origin`.
+pub fn mark_minimap(
+    chunks: &[(ChunkPos, &ChunkStats)],
+    config: &Config,
+    palette: &Palette,
+    scale: i32,
+    origin: (i32, i32, i32),
+) -> SaveData {
+    let min = chunks.iter().fold((i32::MAX, i32::MAX, i32::MAX), |min, (pos, _)| {
+        (min.0.min(pos.0), min.1.min(pos.1), min.2.min(pos.2))
+    });
+
+    let mut bricks = vec![];
+    for (pos, stats) in chunks.iter() {
+        let position = (
+            origin.0 + (pos.0 - min.0) * scale * 2 + scale,
+            origin.1 + (pos.1 - min.1) * scale * 2 + scale,
+            origin.2 + (pos.2 - min.2) * scale * 2 + scale,
+        );
+        let size = (scale as u32, scale as u32, scale as u32);
+        let mut brick = Brick {
+            collision: marker_collision(config),
+            ..marker_brick(position, size, &palette.scale(load_score(stats, config)), true)
+        };
+        if config.marker_interact {
+            add_interact(std::slice::from_mut(&mut brick), &chunk_message(*pos, stats, config));
+        }
+        bricks.push(brick);
+    }
+
+    marker_save(bricks, config)
+}
+
+/// How the markers of a chunk look.
+struct MarkerLook {
+    color: BrickColor,
+    glow: bool,
+    /// How far the sides of corner and edge markers are from their center.
+    size: i32,
+}
+
+/// Returns how big the markers of a chunk with `stats` are: `marker_size`, growing towards
+/// `marker_size_max` as the chunk nears the collider or component limit.
+fn marker_size(stats: Option<&ChunkStats>, config: &Config) -> i32 {
+    let min = config.marker_size.max(1);
+    let max = config.marker_size_max.max(min);
+    let fraction = stats.map_or(0.0, |stats| {
+        (stats.colliders as f64 / COLLIDER_LIMIT as f64)
+            .max(stats.components as f64 / COMPONENT_LIMIT as f64)
+            .min(1.0)
+    });
+    (min as f64 + (max - min) as f64 * fraction).round() as i32
+}
+
+/// Adds the markers of the chunk `pos` to `bricks`, drawn in `style`.
+fn push_markers(
+    bricks: &mut Vec<Brick>,
+    pos: ChunkPos,
+    grid: &Grid,
+    config: &Config,
+    style: MarkerStyle,
+    look: MarkerLook,
+) {
+    let MarkerLook { color, glow, size: t } = look;
+    // markers can't be bigger than the chunk they're in
+    let (x, y, z) = grid.size;
+    let t = t.min(x.min(y).min(z) / 2).max(1);
+    let center = grid.chunk_center(pos);
+    let start = bricks.len();
+    match style {
+        MarkerStyle::Corners => {
+            for i in 0..8 {
+                let position = grid.chunk_corner(i, center, t);
+                bricks.push(marker_brick(position, (t as u32, t as u32, t as u32), &color, glow));
+            }
+        }
+        MarkerStyle::Wedges => {
+            for i in 0..8 {
+                let position = grid.chunk_corner(i, center, t);
+                let corner = (
+                    (position.0 - center.0).signum(),
+                    (position.1 - center.1).signum(),
+                    (position.2 - center.2).signum(),
+                );
+                let (direction, rotation) = wedge_orientation(corner);
+                bricks.push(Brick {
+                    asset_name_index: WEDGE_ASSET,
+                    direction,
+                    rotation,
+                    ..marker_brick(position, (t as u32, t as u32, t as u32), &color, glow)
+                });
+            }
+        }
+        MarkerStyle::Edges => {
+            let center = [center.0, center.1, center.2];
+            let size = [grid.size.0, grid.size.1, grid.size.2];
+            for axis in 0..3 {
+                let segments = (size[axis] + EDGE_SEGMENT_MAX - 1) / EDGE_SEGMENT_MAX;
+                for side in 0..4 {
+                    // the edge runs along `axis`, just inside one side of the chunk on each of
+                    // the other two axes
+                    let mut position = center;
+                    let mut extents = [t; 3];
+                    for (bit, &other) in [(axis + 1) % 3, (axis + 2) % 3].iter().enumerate() {
+                        let sign = if side >> bit & 1 == 0 { -1 } else { 1 };
+                        position[other] = center[other] + sign * (size[other] / 2 - t);
+                    }
+
+                    let start = center[axis] - size[axis] / 2;
+                    for i in 0..segments {
+                        let from = start + size[axis] * i / segments;
+                        let to = start + size[axis] * (i + 1) / segments;
+                        position[axis] = (from + to) / 2;
+                        extents[axis] = ((to - from) / 2).max(1);
+                        bricks.push(marker_brick(
+                            (position[0], position[1], position[2]),
+                            (extents[0] as u32, extents[1] as u32, extents[2] as u32),
+                            &color,
+                            glow,
+                        ));
+                    }
+                }
+            }
+        }
+        MarkerStyle::Fill => {
+            // see-through, and without collision so players can walk around inside, except for
+            // clicking it to print the chunk's stats
+            let color = match color {
+                BrickColor::Unique(color) => BrickColor::Unique(Color {
+                    a: config.marker_fill_alpha,
+                    ..color
+                }),
+                color => color,
+            };
+            let size = ((x / 2).max(1) as u32, (y / 2).max(1) as u32, (z / 2).max(1) as u32);
+            bricks.push(Brick {
+                asset_name_index: FILL_ASSET,
+                material_index: FILL_MATERIAL,
+                collision: Collision {
+                    interaction: config.marker_interact,
+                    ..Collision::for_all(false)
+                },
+                ..marker_brick(center, size, &color, false)
+            });
+        }
+    }
+    if style != MarkerStyle::Fill {
+        for brick in bricks[start..].iter_mut() {
+            brick.collision = marker_collision(config);
+        }
+    }
+}
+
+/// Returns how to turn a corner wedge so its square corner sits in the chunk corner on the sides
+/// `corner` gives the signs of, and its slope faces the middle of the chunk. Unturned, the square
+/// corner of the wedge is its -x -y -z corner.
+fn wedge_orientation((x, y, z): (i32, i32, i32)) -> (Direction, Rotation) {
+    // standing a wedge on its head also flips it along y
+    let (direction, y) = if z < 0 {
+        (Direction::ZPositive, y)
+    } else {
+        (Direction::ZNegative, -y)
+    };
+    let rotation = match (x < 0, y < 0) {
+        (true, true) => Rotation::Deg0,
+        (false, true) => Rotation::Deg90,
+        (false, false) => Rotation::Deg180,
+        (true, false) => Rotation::Deg270,
+    };
+    (direction, rotation)
+}
+
+/// Returns the collision of corner and edge markers and minimaps: none unless
+/// `Config::marker_collision` is on, so they don't snag players and vehicles, except for clicking
+/// them when they tell players about their chunk.
+fn marker_collision(config: &Config) -> Collision {
+    Collision {
+        player: config.marker_collision,
+        weapon: config.marker_collision,
+        interaction: config.marker_collision || config.marker_interact,
+        tool: true,
+    }
+}
+
+/// Describes the chunk `pos` for players clicking its markers: its counts, and which limits it is
+/// over.
+fn chunk_message(pos: ChunkPos, stats: &ChunkStats, config: &Config) -> String {
+    let mut message = format!(
+        "Chunk {:?}: {} bricks, {}/{} colliders, {}/{} components, load score {:.0}/100",
+        pos,
+        stats.bricks,
+        stats.colliders,
+        COLLIDER_LIMIT,
+        stats.components,
+        COMPONENT_LIMIT,
+        load_score(stats, config),
+    );
+    let over = Metric::ALL
+        .iter()
+        .filter(|m| m.limit(config).is_some_and(|limit| m.value(stats) > limit))
+        .map(|m| m.label())
+        .collect::<Vec<_>>();
+    if !over.is_empty() {
+        message += &format!(". Over the limit of {}.", over.join(", "));
+    }
+    message
+}
+
+/// Makes clicking each of `bricks` show `message`.
+fn add_interact(bricks: &mut [Brick], message: &str) {
+    for brick in bricks.iter_mut() {
+        let props = [
+            ("bPlayInteractSound", UnrealType::Boolean(false)),
+            ("Message", UnrealType::String(message.to_owned())),
+            ("ConsoleTag", UnrealType::String(String::new())),
+        ];
+        brick.components.insert(
+            INTERACT_COMPONENT.to_owned(),
+            props.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
+        );
+    }
+}
+
+/// Returns a marker brick at `position` with the half extents `size`.
+fn marker_brick(position: (i32, i32, i32), size: (u32, u32, u32), color: &BrickColor, glow: bool) -> Brick {
+    Brick {
+        owner_index: 1,
+        asset_name_index: 0,
+        material_index: if glow { 0 } else { 1 },
+        material_intensity: 1,
+        color: color.clone(),
+        size: Size::Procedural(size.0, size.1, size.2),
+        position,
+        ..Default::default()
+    }
+}
+
+/// Splits the markers in `data` into saves of the markers of at most `chunks_per_batch` chunks
+/// each, so they can be loaded a few at a time. Markers of a single chunk are never split up.
+pub fn marker_batches(data: SaveData, grid: &Grid, chunks_per_batch: usize, config: &Config) -> Vec<SaveData> {
+    let mut chunks = Vec::<(ChunkPos, Vec<Brick>)>::new();
+    let mut index = HashMap::<ChunkPos, usize>::new();
+    for brick in data.bricks.iter() {
+        let pos = grid.pos_to_chunk(brick.position);
+        let i = *index.entry(pos).or_insert_with(|| {
+            chunks.push((pos, vec![]));
+            chunks.len() - 1
+        });
+        chunks[i].1.push(brick.clone());
+    }
+    if chunks_per_batch == 0 || chunks.len() <= chunks_per_batch {
+        return vec![data];
+    }
+
+    chunks
+        .chunks(chunks_per_batch)
+        .map(|batch| marker_save(batch.iter().flat_map(|(_, bricks)| bricks).cloned().collect(), config))
+        .collect()
+}
+
+/// Returns the owner markers are placed under: the configured one, or the default one if the
+/// configured id isn't a UUID.
+pub fn marker_owner(config: &Config) -> BrickOwner {
+    BrickOwner {
+        id: config
+            .marker_owner_id
+            .parse()
+            .unwrap_or_else(|_| MARKER_OWNER_UUID.parse().unwrap()),
+        name: config.marker_owner_name.clone(),
+        bricks: 0,
+    }
+}
+
+/// How markers were placed, so they can be drawn again with the same options when the chunks
+/// they mark are reanalyzed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Marking {
+    /// The player who placed the markers, whose palette they are drawn in.
+    pub user: String,
+    /// The marking options, like a marker style or `by <metric>`.
+    pub args: Vec<String>,
+    /// What the markers are.
+    pub kind: MarkingKind,
+}
+
+/// What a marking placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkingKind {
+    /// Markers on the chunks themselves.
+    Chunks,
+    /// Tiles at a height, laid by `/chunks markfloor`.
+    Floor(i32),
+    /// A miniature of the build built by `/chunks minimap`, with the half size of each chunk in
+    /// it and the position of its lowest corner.
+    Minimap(i32, (i32, i32, i32)),
+}
+
+/// Returns the owner of the chunk outline that follows `user` around, which is their own so it
+/// can be cleared without touching other markers.
+pub fn outline_owner(user: &str) -> BrickOwner {
+    // FNV-1a, so the id stays the same across restarts
+    let hash = user
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100_0000_01b3));
+    BrickOwner {
+        id: format!("00000000-0000-0000-0001-{:012x}", hash & 0xffff_ffff_ffff)
+            .parse()
+            .unwrap(),
+        name: "Chunk Boundary".into(),
+        bricks: 0,
+    }
+}
+
+/// Outlines the edges of the chunk `pos` for `user`, colored like other markers.
+pub fn mark_outline(
+    pos: ChunkPos,
+    stats: Option<&ChunkStats>,
+    grid: &Grid,
+    config: &Config,
+    palette: &Palette,
+    user: &str,
+) -> SaveData {
+    let mut data = mark_chunks(&[(pos, stats)], grid, config, MarkerStyle::Edges, palette, None, None);
+    data.header2.brick_owners = vec![outline_owner(user)];
+    data
+}
+
+/// Returns the owner of the blinking markers of critical chunks, which are cleared and loaded
+/// again on their own. Its id is the marker owner's with a few bits flipped, so it follows a
+/// configured `marker-owner-id` without ever being the same.
+pub fn blink_owner(config: &Config) -> BrickOwner {
+    let id = marker_owner(config).id.to_string();
+    let group = u16::from_str_radix(&id[19..23], 16).unwrap() ^ BLINK_OWNER_BITS;
+    BrickOwner {
+        id: format!("{}{:04x}{}", &id[..19], group, &id[23..]).parse().unwrap(),
+        name: "Chunk Blink".into(),
+        bricks: 0,
+    }
+}
+
+/// Draws markers a little bigger than the `style` markers of `chunks` in a flashing color, to be
+/// loaded over them and cleared again on a timer so the chunks blink. Fill markers blink at their
+/// corners instead, since a second fill brick would flicker into the first.
+pub fn mark_blink(
+    chunks: &[(ChunkPos, &ChunkStats)],
+    grid: &Grid,
+    config: &Config,
+    style: MarkerStyle,
+) -> SaveData {
+    let style = if style == MarkerStyle::Fill { MarkerStyle::Corners } else { style };
+    let mut bricks = vec![];
+    for (pos, stats) in chunks.iter() {
+        let look = MarkerLook {
+            color: BrickColor::Unique(BLINK_COLOR),
+            glow: true,
+            size: marker_size(Some(stats), config) + 1,
+        };
+        push_markers(&mut bricks, *pos, grid, config, style, look);
+    }
+    let mut data = marker_save(bricks, config);
+    data.header2.brick_owners = vec![blink_owner(config)];
+    data
+}
+
+/// The marker bricks in a chunk, by how they were placed.
+type ChunkMarkers = HashMap<Marking, Vec<Brick>>;
+
+/// The bricks of floors and minimaps, with how they were placed.
+type Overlays = Vec<(Marking, Vec<Brick>)>;
+
+/// What a marking replaced, so it can be undone.
+struct Replaced {
+    /// The markers the chunks it changed held before it. Chunks that had no markers hold `None`.
+    chunks: HashMap<ChunkPos, Option<ChunkMarkers>>,
+    /// The floors and minimaps before it, if it changed them.
+    overlays: Option<Overlays>,
+}
+
+/// The marker bricks currently in the world, by the chunk they are in, so the markers of some
+/// chunks can be removed without losing the rest. Floors and minimaps aren't on the chunks they
+/// show, so they are kept apart and never replace chunk markers.
+#[derive(Default)]
+pub struct PlacedMarkers {
+    /// The grid `chunks` is keyed by.
+    grid: Grid,
+    /// The marker bricks in each chunk, by how they were placed.
+    chunks: HashMap<ChunkPos, ChunkMarkers>,
+    /// The floors laid by `/chunks markfloor` and minimaps built by `/chunks minimap`.
+    overlays: Overlays,
+    /// What each of the latest markings replaced, oldest first, so they can be undone.
+    history: VecDeque<Replaced>,
+}
+
+impl PlacedMarkers {
+    /// Records that `bricks` were placed by `marking`, sorting chunk markers into chunks of
+    /// `grid`. Markers placed before are sorted into that grid too if they were on another one.
+    pub fn add(&mut self, bricks: &[Brick], grid: &Grid, marking: &Marking) {
+        self.regrid(grid);
+        if marking.kind != MarkingKind::Chunks {
+            match self.overlays.iter_mut().find(|(placed_by, _)| placed_by == marking) {
+                Some((_, placed)) => placed.extend_from_slice(bricks),
+                None => self.overlays.push((marking.clone(), bricks.to_vec())),
+            }
+            return;
+        }
+        for brick in bricks.iter() {
+            // every marker brick is centered inside the chunk it marks
+            let pos = self.grid.pos_to_chunk(brick.position);
+            let placed = self.chunks.entry(pos).or_default();
+            match placed.get_mut(marking) {
+                Some(placed) => placed.push(brick.clone()),
+                None => {
+                    placed.insert(marking.clone(), vec![brick.clone()]);
+                }
+            }
+        }
+    }
+
+    /// Sorts the chunk markers into chunks of `grid` if they are on another grid.
+    fn regrid(&mut self, grid: &Grid) {
+        if self.grid == *grid {
+            return;
+        }
+        let placed = self.chunks.drain().flat_map(|(_, placed)| placed).collect::<Vec<_>>();
+        self.grid = *grid;
+        // the chunks markings changed are on the old grid
+        self.history.clear();
+        for (marking, bricks) in placed {
+            self.add(&bricks, grid, &marking);
+        }
+    }
+
+    /// Records that `bricks` were placed by `marking` instead of the markers in the chunks they
+    /// are in, or instead of the floor or minimap placed the same way, or instead of every marker
+    /// with `replace_all`. Returns whether any markers were replaced, in which case every marker
+    /// has to be cleared and loaded again.
+    pub fn place(&mut self, bricks: &[Brick], grid: &Grid, marking: &Marking, replace_all: bool) -> bool {
+        self.regrid(grid);
+        let mut replaced = Replaced {
+            chunks: HashMap::new(),
+            overlays: None,
+        };
+        if replace_all {
+            replaced.chunks = self.chunks.drain().map(|(pos, placed)| (pos, Some(placed))).collect();
+            replaced.overlays = Some(std::mem::take(&mut self.overlays));
+        }
+        if marking.kind == MarkingKind::Chunks {
+            for brick in bricks.iter() {
+                let pos = grid.pos_to_chunk(brick.position);
+                let chunks = &mut self.chunks;
+                replaced.chunks.entry(pos).or_insert_with(|| chunks.remove(&pos));
+            }
+        } else if !replace_all {
+            replaced.overlays = Some(self.overlays.clone());
+            self.overlays.retain(|(placed_by, _)| placed_by.kind != marking.kind);
+        }
+
+        let any_replaced = replaced.chunks.values().any(Option::is_some)
+            || replaced.overlays.as_ref().is_some_and(|overlays| overlays.len() != self.overlays.len());
+        self.history.push_back(replaced);
+        if self.history.len() > UNDO_MAX {
+            self.history.pop_front();
+        }
+
+        self.add(bricks, grid, marking);
+        any_replaced
+    }
+
+    /// Puts the markers changed by the latest marking back the way they were before it. Returns
+    /// false if there is no marking left to undo.
+    pub fn undo(&mut self) -> bool {
+        let replaced = match self.history.pop_back() {
+            Some(replaced) => replaced,
+            None => return false,
+        };
+        for (pos, placed) in replaced.chunks {
+            match placed {
+                Some(placed) => self.chunks.insert(pos, placed),
+                None => self.chunks.remove(&pos),
+            };
+        }
+        if let Some(overlays) = replaced.overlays {
+            self.overlays = overlays;
+        }
+        true
+    }
+
+    /// Returns how many chunks have markers on them, not counting floors and minimaps.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// The grid the markers are sorted into chunks of.
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Returns every way markers were placed, with the chunks they mark. Floors and minimaps mark
+    /// no chunks of their own.
+    pub fn markings(&self) -> Vec<(Marking, Vec<ChunkPos>)> {
+        let mut markings = HashMap::<&Marking, Vec<ChunkPos>>::new();
+        for (pos, placed) in self.chunks.iter() {
+            for marking in placed.keys() {
+                markings.entry(marking).or_default().push(*pos);
+            }
+        }
+        for (marking, _) in self.overlays.iter() {
+            markings.entry(marking).or_default();
+        }
+        markings
+            .into_iter()
+            .map(|(marking, chunks)| (marking.clone(), chunks))
+            .collect()
+    }
+
+    /// Returns whether the chunk `pos` has markers on it.
+    pub fn is_marked(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    /// Forgets the markers of the chunk `pos`, returning whether it had any.
+    pub fn remove(&mut self, pos: ChunkPos) -> bool {
+        self.chunks.remove(&pos).is_some()
+    }
+
+    /// Forgets the markers of every chunk between `min` and `max`, inclusive, and returns how many
+    /// chunks they were in.
+    pub fn remove_region(&mut self, min: ChunkPos, max: ChunkPos) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(|pos, _| {
+            !((min.0..=max.0).contains(&pos.0)
+                && (min.1..=max.1).contains(&pos.1)
+                && (min.2..=max.2).contains(&pos.2))
+        });
+        before - self.chunks.len()
+    }
+
+    /// Forgets every marker, and every marking that could be undone.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.overlays.clear();
+        self.history.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.overlays.is_empty()
+    }
+
+    /// Returns a save of every marker brick, to load them all back in.
+    pub fn save(&self, config: &Config) -> SaveData {
+        let bricks = self
+            .chunks
+            .values()
+            .flat_map(|placed| placed.values())
+            .chain(self.overlays.iter().map(|(_, bricks)| bricks))
+            .flatten()
+            .cloned();
+        marker_save(bricks.collect(), config)
+    }
+}
+
+/// Wraps marker bricks in a save owned by the marker owner, so they can be cleared together.
+fn marker_save(bricks: Vec<Brick>, config: &Config) -> SaveData {
+    let interact_bricks = bricks
+        .iter()
+        .enumerate()
+        .filter(|(_, brick)| brick.components.contains_key(INTERACT_COMPONENT))
+        .map(|(i, _)| i as u32)
+        .collect::<Vec<_>>();
+    let mut components = HashMap::new();
+    if !interact_bricks.is_empty() {
+        let properties = [
+            ("bPlayInteractSound", "Boolean"),
+            ("Message", "String"),
+            ("ConsoleTag", "String"),
+        ];
+        components.insert(INTERACT_COMPONENT.to_owned(), Component {
+            version: 1,
+            brick_indices: interact_bricks,
+            properties: properties.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        });
+    }
+
+    SaveData {
+        header2: Header2 {
+            brick_assets: vec![
+                config.marker_asset.clone(),
+                "PB_DefaultMicroBrick".into(),
+                "PB_DefaultMicroWedgeTriangleCorner".into(),
+            ],
+            materials: vec![
+                config.marker_material.clone(),
+                "BMC_Plastic".into(),
+                config.marker_fill_material.clone(),
+            ],
+            brick_owners: vec![marker_owner(config)],
+            ..Default::default()
+        },
+        bricks,
+        components,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn marking(kind: MarkingKind) -> Marking {
+        Marking {
+            user: "builder".to_owned(),
+            args: vec![],
+            kind,
+        }
+    }
+
+    fn marker(position: (i32, i32, i32)) -> Brick {
+        Brick {
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn bricks(markers: &PlacedMarkers) -> usize {
+        markers.save(&Config::default()).bricks.len()
+    }
+
+    #[test]
+    fn place_replaces_the_markers_of_the_same_chunks() {
+        let grid = Grid::default();
+        let mut markers = PlacedMarkers::default();
+        let chunks = marking(MarkingKind::Chunks);
+
+        assert!(!markers.place(&[marker((512, 512, 512)), marker((1536, 512, 512))], &grid, &chunks, false));
+        assert_eq!(markers.chunk_count(), 2);
+        assert!(markers.place(&[marker((500, 500, 500))], &grid, &chunks, false));
+        assert_eq!(markers.chunk_count(), 2);
+        assert_eq!(bricks(&markers), 2);
+        assert!(markers.is_marked((0, 0, 0)));
+        assert!(!markers.is_marked((0, 0, 1)));
+
+        assert!(markers.place(&[marker((512, 512, 2048))], &grid, &chunks, true));
+        assert_eq!(markers.chunk_count(), 1);
+        assert!(markers.is_marked((0, 0, 2)));
+    }
+
+    #[test]
+    fn floors_and_minimaps_never_replace_chunk_markers() {
+        let grid = Grid::default();
+        let mut markers = PlacedMarkers::default();
+        markers.place(&[marker((512, 512, 0)), marker((1536, 512, 0))], &grid, &marking(MarkingKind::Chunks), false);
+
+        let floor = marking(MarkingKind::Floor(0));
+        assert!(!markers.place(&[marker((512, 512, 0))], &grid, &floor, false));
+        assert!(!markers.place(&[marker((512, 512, 0))], &grid, &marking(MarkingKind::Minimap(2, (0, 0, 0))), false));
+        assert_eq!(markers.chunk_count(), 2);
+        assert_eq!(bricks(&markers), 4);
+        assert_eq!(markers.markings().len(), 3);
+
+        // laying the same floor again replaces the old one
+        assert!(markers.place(&[marker((512, 512, 0))], &grid, &floor, false));
+        assert_eq!(bricks(&markers), 4);
+        assert!(markers.remove((0, 0, 0)));
+        assert_eq!(bricks(&markers), 3);
+    }
+
+    #[test]
+    fn undo_puts_back_what_a_marking_replaced() {
+        let grid = Grid::default();
+        let mut markers = PlacedMarkers::default();
+        let chunks = marking(MarkingKind::Chunks);
+        markers.place(&[marker((512, 512, 512))], &grid, &chunks, false);
+        markers.place(&[marker((500, 500, 500)), marker((1536, 512, 512))], &grid, &chunks, false);
+        markers.place(&[marker((512, 512, 0))], &grid, &marking(MarkingKind::Floor(0)), true);
+        assert_eq!(markers.chunk_count(), 0);
+
+        assert!(markers.undo());
+        assert_eq!(markers.chunk_count(), 2);
+        assert_eq!(bricks(&markers), 2);
+        assert!(markers.undo());
+        assert_eq!(markers.chunk_count(), 1);
+        assert!(markers.is_marked((0, 0, 0)));
+        assert!(markers.undo());
+        assert!(markers.is_empty());
+        assert!(!markers.undo());
+    }
+
+    #[test]
+    fn undo_keeps_only_the_latest_markings() {
+        let grid = Grid::default();
+        let mut markers = PlacedMarkers::default();
+        let chunks = marking(MarkingKind::Chunks);
+        for x in 0..UNDO_MAX as i32 + 2 {
+            markers.place(&[marker((x * 1024 + 512, 512, 512))], &grid, &chunks, false);
+        }
+        for _ in 0..UNDO_MAX {
+            assert!(markers.undo());
+        }
+        assert!(!markers.undo());
+        assert_eq!(markers.chunk_count(), 2);
+    }
+
+    #[test]
+    fn fill_markers_can_be_clicked_only_with_interact_on() {
+        let mut config = Config::default();
+        let stats = ChunkStats { bricks: 10, ..Default::default() };
+        let palette = Palette::new(MarkerPalette::Standard, &config);
+        let chunks = [((0, 0, 0), Some(&stats))];
+        for interact in [true, false].iter() {
+            config.marker_interact = *interact;
+            let data = mark_chunks(&chunks, &Grid::default(), &config, MarkerStyle::Fill, &palette, None, None);
+            let fill = &data.bricks[0];
+            assert_eq!(fill.collision.interaction, *interact);
+            assert!(!fill.collision.player && !fill.collision.weapon);
+            assert_eq!(fill.components.contains_key(INTERACT_COMPONENT), *interact);
+        }
+    }
+
+    #[test]
+    fn remove_region_forgets_only_the_chunks_inside() {
+        let grid = Grid::default();
+        let mut markers = PlacedMarkers::default();
+        let placed = [(0, 0, 0), (1, 1, 0), (2, 0, 0), (0, 0, 1)]
+            .iter()
+            .map(|(x, y, z)| marker((x * 1024 + 512, y * 1024 + 512, z * 1024 + 512)))
+            .collect::<Vec<_>>();
+        markers.place(&placed, &grid, &marking(MarkingKind::Chunks), false);
+        markers.place(&[marker((512, 512, 0))], &grid, &marking(MarkingKind::Floor(0)), false);
+
+        assert_eq!(markers.remove_region((0, 0, 0), (1, 1, 0)), 2);
+        assert!(markers.is_marked((2, 0, 0)) && markers.is_marked((0, 0, 1)));
+        assert_eq!(markers.remove_region((5, 5, 5), (6, 6, 6)), 0);
+        // the floor isn't on the chunks it shows
+        assert_eq!(bricks(&markers), 3);
+    }
+
+    #[test]
+    fn wedges_point_their_square_corner_into_each_chunk_corner() {
+        let mut seen = HashSet::new();
+        for &x in [-1, 1].iter() {
+            for &y in [-1, 1].iter() {
+                for &z in [-1, 1].iter() {
+                    let orientation = wedge_orientation((x, y, z));
+                    // bottom corners stand upright, top corners upside down
+                    let up = if z < 0 { Direction::ZPositive } else { Direction::ZNegative };
+                    assert_eq!(orientation.0, up);
+                    assert!(seen.insert(orientation));
+                }
+            }
+        }
+        assert_eq!(wedge_orientation((-1, -1, -1)), (Direction::ZPositive, Rotation::Deg0));
+    }
+
+
+    #[test]
+    fn heatmap_steps_split_chunks_by_rank() {
+        let config = Config {
+            heatmap_steps: 4,
+            ..Config::default()
+        };
+        let chunks = (0..8)
+            .map(|i| ChunkStats {
+                bricks: i * 100,
+                colliders: i * 100,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let heatmap = Heatmap::new(chunks.iter(), &config);
+        let percents = chunks.iter().map(|stats| heatmap.percent(stats, &config)).collect::<Vec<_>>();
+        // a chunk on a threshold stays in the step below it
+        let steps = percents.iter().map(|p| (p * 3.0 / 100.0).round() as u32).collect::<Vec<_>>();
+        assert_eq!(steps, vec![0, 0, 0, 1, 1, 2, 2, 3]);
+
+        // chunks that all score the same stay at the bottom instead of all standing out
+        let flat = Heatmap::new(chunks[..1].iter().cycle().take(8), &config);
+        assert_eq!(flat.percent(&chunks[0], &config), 0.0);
+
+        let empty = Heatmap::new(std::iter::empty(), &config);
+        assert_eq!(empty.percent(&chunks[7], &config), 0.0);
+    }
+
+
+    #[test]
+    fn colorblind_palettes_tell_states_and_loads_apart_differently() {
+        let config = Config::default();
+        let standard = Palette::new(MarkerPalette::Standard, &config);
+        for &kind in MarkerPalette::ALL.iter().filter(|&&k| k != MarkerPalette::Standard) {
+            let palette = Palette::new(kind, &config);
+            assert_ne!(palette.scale_color(0.0), standard.scale_color(0.0), "{:?}", kind);
+            assert_ne!(palette.scale_color(100.0), standard.scale_color(100.0), "{:?}", kind);
+            assert_ne!(palette.owners, standard.owners, "{:?}", kind);
+        }
+        assert_eq!(standard.scale_color(0.0), Color { r: 0, g: 255, b: 0, a: 255 });
+        assert_eq!(standard.scale_color(50.0), Color { r: 255, g: 255, b: 0, a: 255 });
+        assert_eq!(standard.scale_color(150.0), Color { r: 255, g: 0, b: 0, a: 255 });
+    }
+
+
+    #[test]
+    fn marker_colors_override_the_palette_states() {
+        let config = Config {
+            marker_colors: vec!["OK=#010203".to_owned(), "lights=fff".to_owned(), "nothing=040506".to_owned()],
+            ..Config::default()
+        };
+        let palette = Palette::new(MarkerPalette::Tritanopia, &config);
+        let default = Palette::new(MarkerPalette::Tritanopia, &Config::default());
+        let ok = MARKER_STATES.iter().position(|s| *s == "ok").unwrap();
+        for (i, color) in palette.states.iter().enumerate() {
+            if i == ok {
+                assert_eq!(*color, Color { r: 1, g: 2, b: 3, a: 255 });
+            } else {
+                // a malformed color or unknown state changes nothing
+                assert_eq!(*color, default.states[i], "{}", MARKER_STATES[i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    analysis::{AnalyzedSave, ChunkStats, COLLIDER_LIMIT, COMPONENT_LIMIT},
+    config::Config,
+};
+
+/// A per-chunk number that chunks can be compared, sorted, and limited by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Bricks,
+    Colliders,
+    Components,
+    Wires,
+    Lights,
+    AudioEmitters,
+    Spawners,
+    Interacts,
+    Emitters,
+    GlowBricks,
+    Payload,
+    Density,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 12] = [
+        Metric::Bricks,
+        Metric::Colliders,
+        Metric::Components,
+        Metric::Wires,
+        Metric::Lights,
+        Metric::AudioEmitters,
+        Metric::Spawners,
+        Metric::Interacts,
+        Metric::Emitters,
+        Metric::GlowBricks,
+        Metric::Payload,
+        Metric::Density,
+    ];
+
+    /// The name used to refer to the metric in commands.
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Bricks => "bricks",
+            Metric::Colliders => "colliders",
+            Metric::Components => "components",
+            Metric::Wires => "wires",
+            Metric::Lights => "lights",
+            Metric::AudioEmitters => "audio",
+            Metric::Spawners => "spawners",
+            Metric::Interacts => "interacts",
+            Metric::Emitters => "emitters",
+            Metric::GlowBricks => "glow",
+            Metric::Payload => "payload",
+            Metric::Density => "density",
+        }
+    }
+
+    /// A human readable description of what the metric counts.
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::Bricks => "bricks",
+            Metric::Colliders => "colliders",
+            Metric::Components => "components",
+            Metric::Wires => "wire/logic components",
+            Metric::Lights => "lights",
+            Metric::AudioEmitters => "audio emitters",
+            Metric::Spawners => "item spawners",
+            Metric::Interacts => "interact components",
+            Metric::Emitters => "particle emitters",
+            Metric::GlowBricks => "glowing bricks",
+            Metric::Payload => "bytes of component data",
+            Metric::Density => "bricks per 1000 cubic studs",
+        }
+    }
+
+    /// Finds the metric called `name`.
+    pub fn from_name(name: &str) -> Option<Metric> {
+        Metric::ALL
+            .iter()
+            .copied()
+            .find(|m| m.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the names of every metric, for listing in help and error messages.
+    pub fn names() -> String {
+        Metric::ALL
+            .iter()
+            .map(|m| m.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns the metric's value for a chunk.
+    pub fn value(self, stats: &ChunkStats) -> u32 {
+        match self {
+            Metric::Bricks => stats.bricks,
+            Metric::Colliders => stats.colliders,
+            Metric::Components => stats.components,
+            Metric::Wires => stats.wires,
+            Metric::Lights => stats.lights,
+            Metric::AudioEmitters => stats.audio_emitters,
+            Metric::Spawners => stats.spawners,
+            Metric::Interacts => stats.interacts,
+            Metric::Emitters => stats.emitters,
+            Metric::GlowBricks => stats.glow_bricks,
+            Metric::Payload => stats.component_bytes.min(u32::MAX as u64) as u32,
+            Metric::Density => (stats.density() * 1000.0).round() as u32,
+        }
+    }
+
+    /// Returns the most a chunk can have of this metric before it is over the limit, if the
+    /// metric is limited at all.
+    pub fn limit(self, config: &Config) -> Option<u32> {
+        match self {
+            Metric::Bricks | Metric::Density => None,
+            Metric::Colliders => Some(COLLIDER_LIMIT),
+            Metric::Components => Some(COMPONENT_LIMIT),
+            Metric::Wires => Some(config.wire_limit),
+            Metric::Lights => Some(config.light_limit),
+            Metric::AudioEmitters => Some(config.audio_limit),
+            Metric::Spawners => Some(config.spawner_limit),
+            Metric::Interacts => Some(config.interact_limit),
+            Metric::Emitters => Some(config.emitter_limit),
+            Metric::GlowBricks => Some(config.glow_limit),
+            Metric::Payload => Some(config.payload_limit),
+        }
+    }
+
+    /// Returns the metric's value for a chunk as a fraction of its limit, if it is limited.
+    pub fn fraction(self, stats: &ChunkStats, config: &Config) -> Option<f64> {
+        self.limit(config)
+            .map(|limit| self.value(stats) as f64 / limit.max(1) as f64)
+    }
+}
+
+/// Returns a chunk's load score from 0 to 100: the weighted average of how close its bricks,
+/// colliders, components, lights, and wires are to their limits, each capped at the limit.
+pub fn load_score(stats: &ChunkStats, config: &Config) -> f64 {
+    let terms = [
+        (Metric::Bricks, config.load_weight_bricks, config.load_brick_budget),
+        (Metric::Colliders, config.load_weight_colliders, COLLIDER_LIMIT),
+        (Metric::Components, config.load_weight_components, COMPONENT_LIMIT),
+        (Metric::Lights, config.load_weight_lights, config.light_limit),
+        (Metric::Wires, config.load_weight_wires, config.wire_limit),
+    ];
+    let total = terms.iter().map(|(_, weight, _)| weight.max(0.0)).sum::<f64>();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    terms
+        .iter()
+        .map(|&(metric, weight, full)| {
+            weight.max(0.0) * (metric.value(stats) as f64 / full.max(1) as f64).min(1.0)
+        })
+        .sum::<f64>()
+        / total
+        * 100.0
+}
+
+/// Returns how close a chunk is to its worst limit, as the largest fraction of any limit, along
+/// with the metric that fraction belongs to.
+pub fn severity(stats: &ChunkStats, config: &Config) -> (f64, Metric) {
+    Metric::ALL
+        .iter()
+        .filter_map(|&m| m.fraction(stats, config).map(|f| (f, m)))
+        .fold((0.0, Metric::Colliders), |worst, next| {
+            if next.0 > worst.0 {
+                next
+            } else {
+                worst
+            }
+        })
+}
+
+/// Returns the health of a whole analysis from 0 to 100, starting from 100 and losing up to 40
+/// for the fraction of chunks over any limit (all 40 at a fifth of them), up to 30 for how far
+/// the worst chunk is over its worst limit (all 30 at double the limit), and up to 30 for how
+/// close the save's total colliders and components are to the server budgets.
+pub fn health_score(save: &AnalyzedSave, config: &Config) -> f64 {
+    let severities = save
+        .chunks
+        .values()
+        .map(|stats| severity(stats, config).0)
+        .collect::<Vec<_>>();
+    let over = severities.iter().filter(|&&s| s > 1.0).count() as f64 / severities.len().max(1) as f64;
+    let worst = severities.iter().copied().fold(0.0, f64::max);
+
+    let total = |metric: Metric| save.chunks.values().map(|s| metric.value(s) as f64).sum::<f64>();
+    let totals = (total(Metric::Colliders) / config.server_collider_budget.max(1) as f64)
+        .max(total(Metric::Components) / config.server_component_budget.max(1) as f64);
+
+    100.0
+        - 40.0 * (over * 5.0).min(1.0)
+        - 30.0 * (worst - 1.0).clamp(0.0, 1.0)
+        - 30.0 * totals.min(1.0)
+}
+
+/// Describes a health score (see `health_score`) in a word, along with the color to show it in.
+pub fn health_rating(score: f64) -> (&'static str, &'static str) {
+    match score {
+        s if s >= 80.0 => ("good", "0a0"),
+        s if s >= 60.0 => ("fair", "aa0"),
+        s if s >= 40.0 => ("poor", "a50"),
+        _ => ("critical", "a00"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Returns a chunk with `n` times each limit the load score counts.
+    fn loaded(n: f64) -> ChunkStats {
+        let config = Config::default();
+        let of = |limit: u32| (limit as f64 * n) as u32;
+        ChunkStats {
+            bricks: of(config.load_brick_budget),
+            colliders: of(COLLIDER_LIMIT),
+            components: of(COMPONENT_LIMIT),
+            lights: of(config.light_limit),
+            wires: of(config.wire_limit),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn load_score_weighs_each_limit_up_to_full() {
+        let config = Config::default();
+        assert_eq!(load_score(&ChunkStats::default(), &config), 0.0);
+        assert!((load_score(&loaded(0.4), &config) - 40.0).abs() < 1e-9);
+        assert_eq!(load_score(&loaded(1.0), &config), 100.0);
+        assert_eq!(load_score(&loaded(3.0), &config), 100.0);
+
+        // colliders weigh three times as much as lights by default
+        let colliders = ChunkStats {
+            colliders: COLLIDER_LIMIT,
+            ..Default::default()
+        };
+        let lights = ChunkStats {
+            lights: config.light_limit,
+            ..Default::default()
+        };
+        assert_eq!(load_score(&colliders, &config), 3.0 * load_score(&lights, &config));
+
+        let unweighted = Config {
+            load_weight_bricks: 0.0,
+            load_weight_colliders: 0.0,
+            load_weight_components: -1.0,
+            load_weight_lights: 0.0,
+            load_weight_wires: 0.0,
+            ..Config::default()
+        };
+        assert_eq!(load_score(&loaded(1.0), &unweighted), 0.0);
+    }
+
+
+    #[test]
+    fn severity_picks_the_limit_a_chunk_is_closest_to() {
+        let config = Config::default();
+        assert_eq!(severity(&ChunkStats::default(), &config), (0.0, Metric::Colliders));
+
+        let stats = ChunkStats {
+            bricks: 1_000_000,
+            colliders: COLLIDER_LIMIT / 2,
+            lights: config.light_limit * 3 / 2,
+            ..Default::default()
+        };
+        // bricks have no limit, however many there are
+        assert_eq!(severity(&stats, &config), (1.5, Metric::Lights));
+
+        // a chunk right at a limit isn't over it yet
+        let full = ChunkStats {
+            colliders: COLLIDER_LIMIT,
+            components: COMPONENT_LIMIT - 1,
+            ..Default::default()
+        };
+        assert_eq!(severity(&full, &config), (1.0, Metric::Colliders));
+    }
+
+
+    /// Returns an analysis of a save with `chunks` lined up along the x axis.
+    fn analysis(chunks: Vec<ChunkStats>) -> AnalyzedSave {
+        AnalyzedSave {
+            brick_count: chunks.iter().map(|stats| stats.bricks).sum(),
+            chunks: chunks.into_iter().enumerate().map(|(x, stats)| ((x as i32, 0, 0), stats)).collect(),
+            duplicate_bricks: 0,
+            owners: vec![],
+            filter: Default::default(),
+            grid: Default::default(),
+            assets: vec![],
+            unknown_assets: vec![],
+            analyzed_at: 0,
+            restored: false,
+            imported: false,
+        }
+    }
+
+    #[test]
+    fn health_score_loses_points_for_chunks_over_limits() {
+        let config = Config::default();
+        let quiet = || ChunkStats {
+            bricks: 100,
+            colliders: 100,
+            ..Default::default()
+        };
+        assert_eq!(health_score(&analysis(vec![]), &config), 100.0);
+
+        let mut chunks = (0..10).map(|_| quiet()).collect::<Vec<_>>();
+        let healthy = health_score(&analysis(chunks.clone()), &config);
+        assert!(healthy > 99.0 && healthy < 100.0, "{}", healthy);
+        assert_eq!(health_rating(healthy).0, "good");
+
+        // a tenth of the chunks over a limit costs half of the 40 points for them
+        chunks[0].lights = config.light_limit + 1;
+        let one_over = health_score(&analysis(chunks.clone()), &config);
+        assert!((healthy - one_over - 20.0 - 1.5).abs() < 1e-9, "{}", one_over);
+        assert_eq!(health_rating(one_over).0, "fair");
+
+        // double a limit costs all 30 points for the worst chunk, and no more past that
+        chunks[1].lights = config.light_limit * 2;
+        let worse = health_score(&analysis(chunks.clone()), &config);
+        chunks[1].lights = config.light_limit * 10;
+        assert_eq!(health_score(&analysis(chunks.clone()), &config), worse);
+        assert!((healthy - worse - 40.0 - 30.0).abs() < 1e-9, "{}", worse);
+        assert_eq!(health_rating(worse).0, "critical");
+    }
+
+    #[test]
+    fn health_score_counts_the_server_budgets() {
+        let config = Config::default();
+        let half = ChunkStats {
+            colliders: (config.server_collider_budget / 2) as u32,
+            ..Default::default()
+        };
+        // the chunk is far over the collider limit too
+        let score = health_score(&analysis(vec![half]), &config);
+        assert!((score - (100.0 - 40.0 - 30.0 - 15.0)).abs() < 1e-9, "{}", score);
+    }
+}
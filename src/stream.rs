@@ -0,0 +1,592 @@
+//! A streaming reader for BRS saves.
+//!
+//! `brickadia`'s `SaveReader` can only read every brick of a save into memory at once, which is
+//! costly for saves with millions of bricks. This reader still uses `SaveReader` for the headers,
+//! but then decodes the brick and component sections one brick at a time, handing each one to a
+//! callback instead of collecting them.
+
+use std::{
+    cmp,
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+    io::{self, BufReader, Cursor, Read},
+};
+
+use anyhow::{anyhow, Result};
+use bitstream_io::{BitRead, BitReader, LittleEndian};
+use brickadia::{
+    read::SaveReader,
+    save::{
+        Brick, BrickColor, Collision, Color, Direction, Header1, Header2, Rotation, Size,
+        UnrealType,
+    },
+};
+use byteorder::ReadBytesExt;
+use flate2::read::ZlibDecoder;
+
+/// A save whose headers have been read, but whose bricks have not.
+pub struct SaveStream<R: Read> {
+    reader: R,
+    pub version: u16,
+    pub header1: Header1,
+    pub header2: Header2,
+}
+
+impl<R: Read> SaveStream<R> {
+    /// Read the headers of a save from `reader`, skipping its preview.
+    pub fn open(mut reader: R) -> Result<Self> {
+        let mut save_reader = SaveReader::new(&mut reader)?;
+        let version = save_reader.version;
+        let header1 = save_reader.read_header1()?;
+        let header2 = save_reader.read_header2()?;
+        save_reader.skip_preview()?;
+
+        Ok(Self {
+            reader,
+            version,
+            header1,
+            header2,
+        })
+    }
+
+    /// Hashes everything in the save that affects its analysis: the brick count, the second
+    /// header, and the raw brick and component sections. The rest of the first header is left
+    /// out since it holds the time the save was written, so saving an unchanged world again
+    /// gives the same hash.
+    pub fn content_hash(mut self) -> Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.header1.brick_count.hash(&mut hasher);
+        format!("{:?}", self.header2).hash(&mut hasher);
+        let mut buf = [0; 64 * 1024];
+        loop {
+            let n = self.reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.write(&buf[..n]);
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Read the bricks of the save, passing each one to `f` without its components.
+    ///
+    /// Returns the number of bricks read, which must be passed to `read_components`.
+    pub fn read_bricks(&mut self, mut f: impl FnMut(Brick)) -> Result<usize> {
+        let (uncompressed_size, compressed_size) = read_section_sizes(&mut self.reader)?;
+        let mut section = (&mut self.reader).take(if compressed_size == 0 {
+            uncompressed_size
+        } else {
+            compressed_size
+        });
+
+        let decoded: Box<dyn Read + '_> = if compressed_size == 0 {
+            Box::new(&mut section)
+        } else {
+            Box::new(ZlibDecoder::new(&mut section))
+        };
+        let mut bits = BitReader::endian(
+            CountingReader {
+                inner: BufReader::new(decoded),
+                count: 0,
+            },
+            LittleEndian,
+        );
+
+        let version = self.version;
+        let header2 = &self.header2;
+        let brick_asset_count = cmp::max(header2.brick_assets.len(), 2) as u32;
+        let material_count = cmp::max(header2.materials.len(), 2) as u32;
+        let physical_material_count = cmp::max(header2.physical_materials.len(), 2) as u32;
+
+        let mut count = 0;
+        loop {
+            // align and stop once we've read every brick or the whole section
+            bits.byte_align();
+            if count >= self.header1.brick_count as usize
+                || bits.reader().unwrap().count >= uncompressed_size
+            {
+                break;
+            }
+
+            let asset_name_index = read_uint(&mut bits, brick_asset_count)?;
+
+            let size = match bits.read_bit()? {
+                true => Size::Procedural(
+                    read_uint_packed(&mut bits)?,
+                    read_uint_packed(&mut bits)?,
+                    read_uint_packed(&mut bits)?,
+                ),
+                false => Size::Empty,
+            };
+
+            let position = (
+                read_int_packed(&mut bits)?,
+                read_int_packed(&mut bits)?,
+                read_int_packed(&mut bits)?,
+            );
+
+            let orientation = read_uint(&mut bits, 24)?;
+            let direction = Direction::try_from(((orientation >> 2) % 6) as u8).unwrap();
+            let rotation = Rotation::try_from((orientation & 3) as u8).unwrap();
+
+            let collision = if version >= 10 {
+                Collision {
+                    player: bits.read_bit()?,
+                    weapon: bits.read_bit()?,
+                    interaction: bits.read_bit()?,
+                    tool: bits.read_bit()?,
+                }
+            } else {
+                Collision::for_all(bits.read_bit()?)
+            };
+
+            let visibility = bits.read_bit()?;
+
+            let material_index = if version >= 8 {
+                read_uint(&mut bits, material_count)?
+            } else if bits.read_bit()? {
+                read_uint_packed(&mut bits)?
+            } else {
+                1
+            };
+
+            let (physical_index, material_intensity) = if version >= 9 {
+                (
+                    read_uint(&mut bits, physical_material_count)?,
+                    read_uint(&mut bits, 11)?,
+                )
+            } else {
+                (0, 5)
+            };
+
+            let color = match bits.read_bit()? {
+                true if version >= 9 => {
+                    let mut bytes = [0u8; 3];
+                    bits.read_bytes(&mut bytes)?;
+                    BrickColor::Unique(Color::from_bytes_rgb(bytes))
+                }
+                true => {
+                    let mut bytes = [0u8; 4];
+                    bits.read_bytes(&mut bytes)?;
+                    BrickColor::Unique(Color::from_bytes_bgra(bytes))
+                }
+                false => BrickColor::Index(read_uint(&mut bits, header2.colors.len() as u32)?),
+            };
+
+            let owner_index = if version >= 3 {
+                read_uint_packed(&mut bits)?
+            } else {
+                0
+            };
+
+            f(Brick {
+                asset_name_index,
+                size,
+                position,
+                direction,
+                rotation,
+                collision,
+                visibility,
+                material_index,
+                physical_index,
+                material_intensity,
+                color,
+                owner_index,
+                components: HashMap::new(),
+            });
+            count += 1;
+        }
+
+        // skip whatever is left of the section so the reader is positioned at the components
+        drop(bits);
+        io::copy(&mut section, &mut io::sink())?;
+
+        Ok(count)
+    }
+
+    /// Read the components of the save, passing each brick index, component name, and the
+    /// component's properties on that brick to `f`.
+    ///
+    /// `brick_count` is the number of bricks returned by `read_bricks`.
+    pub fn read_components(
+        &mut self,
+        brick_count: usize,
+        mut f: impl FnMut(usize, &str, &HashMap<String, UnrealType>),
+    ) -> Result<()> {
+        if self.version < 8 {
+            return Ok(());
+        }
+
+        // read up to the size instead of allocating it up front, since a corrupt save can claim
+        // any size
+        let (uncompressed_size, compressed_size) = read_section_sizes(&mut self.reader)?;
+        let mut bytes = vec![];
+        if compressed_size == 0 {
+            (&mut self.reader).take(uncompressed_size).read_to_end(&mut bytes)?;
+        } else {
+            ZlibDecoder::new((&mut self.reader).take(compressed_size))
+                .take(uncompressed_size)
+                .read_to_end(&mut bytes)?;
+        }
+        if bytes.len() as u64 != uncompressed_size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let index_max = cmp::max(brick_count, 2) as u32;
+        for _ in 0..cursor.read_i32::<byteorder::LittleEndian>()? {
+            let name = read_byte_string(&mut cursor)?;
+
+            let mut bit_bytes = vec![0u8; read_length(&mut cursor)?];
+            cursor.read_exact(&mut bit_bytes)?;
+            let bit_len = bit_bytes.len();
+            let mut bits = BitReader::endian(Cursor::new(bit_bytes), LittleEndian);
+
+            let _version = read_i32_le(&mut bits)?;
+            let brick_indices = (0..read_i32_le(&mut bits)?)
+                .map(|_| read_uint(&mut bits, index_max))
+                .collect::<io::Result<Vec<_>>>()?;
+            let properties = (0..read_i32_le(&mut bits)?)
+                .map(|_| Ok((read_string(&mut bits, bit_len)?, read_string(&mut bits, bit_len)?)))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            for &i in brick_indices.iter() {
+                let mut props = HashMap::new();
+                for (n, ty) in properties.iter() {
+                    props.insert(n.to_owned(), read_unreal_type(&mut bits, ty, bit_len)?);
+                }
+                if (i as usize) < brick_count {
+                    f(i as usize, &name, &props);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A reader that counts how many bytes have been read through it.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// Read the uncompressed and compressed sizes that prefix a compressed section.
+fn read_section_sizes(reader: &mut impl Read) -> Result<(u64, u64)> {
+    let uncompressed_size = reader.read_i32::<byteorder::LittleEndian>()?;
+    let compressed_size = reader.read_i32::<byteorder::LittleEndian>()?;
+    if uncompressed_size < 0 || compressed_size < 0 || compressed_size >= uncompressed_size {
+        return Err(anyhow!("invalid compressed section"));
+    }
+    Ok((uncompressed_size as u64, compressed_size as u64))
+}
+
+fn read_uint(bits: &mut impl BitRead, max: u32) -> io::Result<u32> {
+    let mut value = 0;
+    let mut mask = 1;
+
+    while value + mask < max && mask != 0 {
+        if bits.read_bit()? {
+            value |= mask;
+        }
+        mask <<= 1;
+    }
+
+    Ok(value)
+}
+
+fn read_uint_packed(bits: &mut impl BitRead) -> io::Result<u32> {
+    let mut value = 0;
+
+    for i in 0..5 {
+        let has_next = bits.read_bit()?;
+        let mut part = 0;
+        for shift in 0..7 {
+            part |= (bits.read_bit()? as u32) << shift;
+        }
+        value |= part << (7 * i);
+        if !has_next {
+            break;
+        }
+    }
+
+    Ok(value)
+}
+
+fn read_int_packed(bits: &mut impl BitRead) -> io::Result<i32> {
+    let value = read_uint_packed(bits)?;
+    Ok((value >> 1) as i32 * if value & 1 != 0 { 1 } else { -1 })
+}
+
+fn read_i32_le(bits: &mut impl BitRead) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    bits.read_bytes(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_f32_le(bits: &mut impl BitRead) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    bits.read_bytes(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+/// Reads a length from the components section, rejecting lengths that are negative or longer
+/// than what is left of it, which only a corrupt save has.
+fn read_length(cursor: &mut Cursor<Vec<u8>>) -> io::Result<usize> {
+    let length = cursor.read_i32::<byteorder::LittleEndian>()?;
+    let left = cursor.get_ref().len() as u64 - cursor.position();
+    if length < 0 || length as u64 > left {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid length"));
+    }
+    Ok(length as usize)
+}
+
+/// Decode an Unreal string of `size` (as stored in the save) using `read_bytes`. Negative sizes
+/// are UCS-2 strings of `ucs2_len(size)` characters. Strings longer than `max` bytes are
+/// rejected before anything is allocated for them.
+fn decode_string(
+    size: i32,
+    max: usize,
+    ucs2_len: impl FnOnce(i32) -> io::Result<usize>,
+    mut read_bytes: impl FnMut(&mut [u8]) -> io::Result<()>,
+) -> io::Result<String> {
+    let too_long = || io::Error::new(io::ErrorKind::InvalidData, "string longer than its section");
+    if size >= 0 {
+        if size as usize > max {
+            return Err(too_long());
+        }
+        let mut chars = vec![0u8; cmp::max(0, size - 1) as usize];
+        read_bytes(&mut chars)?;
+        if size > 0 {
+            // read a null terminator
+            read_bytes(&mut [0])?;
+        }
+        String::from_utf8(chars)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid string data"))
+    } else {
+        let len = ucs2_len(size)?;
+        if len > max / 2 {
+            return Err(too_long());
+        }
+        let mut bytes = vec![0u8; len * 2];
+        read_bytes(&mut bytes)?;
+        let chars = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16(&chars)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid UCS-2 string data"))
+    }
+}
+
+fn read_string(bits: &mut impl BitRead, max: usize) -> io::Result<String> {
+    let size = read_i32_le(bits)?;
+    decode_string(size, max, |size| Ok(size.unsigned_abs() as usize), |buf| bits.read_bytes(buf))
+}
+
+fn read_byte_string(cursor: &mut Cursor<Vec<u8>>) -> io::Result<String> {
+    let size = cursor.read_i32::<byteorder::LittleEndian>()?;
+    let left = (cursor.get_ref().len() as u64 - cursor.position()) as usize;
+    decode_string(
+        size,
+        left,
+        |size| match size.unsigned_abs() % 2 {
+            0 => Ok(size.unsigned_abs() as usize / 2),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid UCS-2 size")),
+        },
+        |buf| cursor.read_exact(buf),
+    )
+}
+
+fn read_unreal_type(bits: &mut impl BitRead, ty: &str, max: usize) -> io::Result<UnrealType> {
+    match ty {
+        "Class" | "Object" => Ok(UnrealType::Class(read_string(bits, max)?)),
+        "String" => Ok(UnrealType::String(read_string(bits, max)?)),
+        "Boolean" => Ok(UnrealType::Boolean(read_i32_le(bits)? != 0)),
+        "Float" => Ok(UnrealType::Float(read_f32_le(bits)?)),
+        "Color" => {
+            let mut bytes = [0u8; 4];
+            bits.read_bytes(&mut bytes)?;
+            Ok(UnrealType::Color(Color::from_bytes_bgra(bytes)))
+        }
+        "Byte" => {
+            let mut byte = [0u8; 1];
+            bits.read_bytes(&mut byte)?;
+            Ok(UnrealType::Byte(byte[0]))
+        }
+        "Rotator" => Ok(UnrealType::Rotator(
+            read_f32_le(bits)?,
+            read_f32_le(bits)?,
+            read_f32_le(bits)?,
+        )),
+        invalid => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid unreal type specified: {}", invalid),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brickadia::{
+        save::{Brick, BrickOwner, Component, SaveData},
+        write::SaveWriter,
+    };
+
+    use super::*;
+
+    /// Builds a save with bricks of every orientation, size, color kind, and collision, some of
+    /// them with components.
+    fn test_save() -> SaveData {
+        let directions = [
+            Direction::XPositive,
+            Direction::XNegative,
+            Direction::YPositive,
+            Direction::YNegative,
+            Direction::ZPositive,
+            Direction::ZNegative,
+        ];
+        let rotations = [Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270];
+        let mut bricks = vec![];
+        for i in 0..600 {
+            let mut components = HashMap::new();
+            if i % 7 == 0 {
+                let mut light = HashMap::new();
+                light.insert("Radius".to_owned(), UnrealType::Float(100.0 + i as f32));
+                light.insert("bCastShadows".to_owned(), UnrealType::Boolean(i % 2 == 0));
+                light.insert("Color".to_owned(), UnrealType::Color(Color { r: 1, g: 2, b: 3, a: 4 }));
+                components.insert("BCD_PointLight".to_owned(), light);
+            }
+            if i % 11 == 0 {
+                let mut interact = HashMap::new();
+                interact.insert("Message".to_owned(), UnrealType::String(format!("hello {}", i)));
+                interact.insert("ConsoleTag".to_owned(), UnrealType::String(String::new()));
+                components.insert("BCD_Interact".to_owned(), interact);
+            }
+            bricks.push(Brick {
+                asset_name_index: i % 2,
+                size: if i % 2 == 0 { Size::Procedural(5, i % 9 + 1, 300) } else { Size::Empty },
+                position: (i as i32 * 37 - 9000, -(i as i32) * 1013, i as i32 % 500 - 250),
+                direction: directions[i as usize % 6].clone(),
+                rotation: rotations[i as usize % 4].clone(),
+                collision: Collision {
+                    player: i % 2 == 0,
+                    weapon: i % 3 == 0,
+                    interaction: i % 5 == 0,
+                    tool: true,
+                },
+                visibility: i % 13 != 0,
+                material_index: i % 2,
+                material_intensity: i % 11,
+                color: if i % 4 == 0 {
+                    BrickColor::Unique(Color { r: 9, g: 8, b: i as u8, a: 255 })
+                } else {
+                    BrickColor::Index(i % 3)
+                },
+                owner_index: i % 3,
+                components,
+                ..Default::default()
+            });
+        }
+
+        let mut light = Component::default();
+        for (name, ty) in [("Radius", "Float"), ("bCastShadows", "Boolean"), ("Color", "Color")].iter() {
+            light.properties.insert(name.to_string(), ty.to_string());
+        }
+        let mut interact = Component::default();
+        for (name, ty) in [("Message", "String"), ("ConsoleTag", "String")].iter() {
+            interact.properties.insert(name.to_string(), ty.to_string());
+        }
+        let mut components = HashMap::new();
+        components.insert("BCD_PointLight".to_owned(), light);
+        components.insert("BCD_Interact".to_owned(), interact);
+
+        let mut data = SaveData {
+            bricks,
+            components,
+            ..Default::default()
+        };
+        data.header1.brick_count = data.bricks.len() as u32;
+        data.header2.brick_assets = vec!["PB_DefaultBrick".into(), "B_1x1_Round".into()];
+        data.header2.materials = vec!["BMC_Plastic".into(), "BMC_Glow".into()];
+        data.header2.colors = vec![Color { r: 1, g: 1, b: 1, a: 255 }; 3];
+        data.header2.brick_owners = ["a", "b"]
+            .iter()
+            .map(|name| BrickOwner {
+                id: Default::default(),
+                name: name.to_string(),
+                bricks: 1,
+            })
+            .collect();
+        data
+    }
+
+    /// Returns everything about a brick but its components, which `Brick` can't compare.
+    fn brick_fields(brick: &Brick) -> String {
+        format!(
+            "{:?}",
+            (
+                brick.asset_name_index,
+                &brick.size,
+                brick.position,
+                &brick.direction,
+                &brick.rotation,
+                &brick.collision,
+                brick.visibility,
+                brick.material_index,
+                brick.material_intensity,
+                &brick.color,
+                brick.owner_index,
+            ),
+        )
+    }
+
+    #[test]
+    fn streams_the_same_bricks_as_save_reader() {
+        let mut bytes = vec![];
+        SaveWriter::new(&mut bytes, test_save()).write().unwrap();
+        let read = SaveReader::new(&bytes[..]).unwrap().read_all_skip_preview().unwrap();
+
+        let mut stream = SaveStream::open(&bytes[..]).unwrap();
+        let mut streamed = vec![];
+        let count = stream.read_bricks(|brick| streamed.push(brick)).unwrap();
+        stream
+            .read_components(count, |i, name, properties| {
+                streamed[i].components.insert(name.to_owned(), properties.clone());
+            })
+            .unwrap();
+
+        assert_eq!(count, read.bricks.len());
+        assert_eq!(streamed.len(), read.bricks.len());
+        for (read, streamed) in read.bricks.iter().zip(streamed.iter()) {
+            assert_eq!(brick_fields(streamed), brick_fields(read));
+            assert_eq!(streamed.components, read.components);
+        }
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_end_of_the_section() {
+        let section = |length: i32| {
+            let mut bytes = length.to_le_bytes().to_vec();
+            bytes.extend_from_slice(b"ab\0");
+            Cursor::new(bytes)
+        };
+        assert_eq!(read_length(&mut section(3)).unwrap(), 3);
+        assert!(read_length(&mut section(4)).is_err());
+        assert!(read_length(&mut section(-1)).is_err());
+        assert!(read_length(&mut section(i32::MAX)).is_err());
+
+        assert_eq!(read_byte_string(&mut section(3)).unwrap(), "ab");
+        assert!(read_byte_string(&mut section(i32::MAX)).is_err());
+        assert!(read_byte_string(&mut section(i32::MIN)).is_err());
+    }
+}